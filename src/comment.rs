@@ -0,0 +1,397 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Formatting of comments: `//`, `///`, `//!`, `/* */`, `/** */` and `/*! */`.
+
+use utils::make_indent;
+use DocCommentStyle;
+
+use syntax::ast;
+use syntax::parse::lexer::comments;
+
+// Rewrite a single comment which starts at column offset and may use width
+// columns. Line comments which are too wide are wrapped (if the wrap_comments
+// option is set), each new line keeping the comment's prefix. Block comments
+// are re-indented so that continuation lines line up under the opening `/*`.
+pub fn rewrite_comment(orig: &str, width: usize, offset: usize) -> String {
+    let orig = orig.trim();
+    if orig.starts_with("/*") {
+        rewrite_block_comment(orig, offset)
+    } else {
+        rewrite_line_comment(orig, width, offset)
+    }
+}
+
+fn rewrite_line_comment(orig: &str, width: usize, offset: usize) -> String {
+    if orig.len() <= width || orig.contains('\n') || !config!(wrap_comments) {
+        return orig.to_owned();
+    }
+
+    // Keep the comment's own prefix, e.g., `//`, `///`, `//!` or `////`.
+    let prefix_len = if orig.starts_with("//!") {
+        3
+    } else {
+        orig.find(|c: char| c != '/').unwrap_or(orig.len())
+    };
+    let prefix = format!("{} ", &orig[..prefix_len]);
+    let text = orig[prefix_len..].trim();
+    // We'd rather overflow than wrap into a column of single words.
+    let text_width = width.saturating_sub(prefix.len());
+    if text_width == 0 {
+        return orig.to_owned();
+    }
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split(char::is_whitespace).filter(|w| w.len() > 0) {
+        if line.len() > 0 && line.len() + 1 + word.len() > text_width {
+            lines.push(line);
+            line = String::new();
+        }
+        if line.len() > 0 {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    lines.push(line);
+
+    let indent = make_indent(offset);
+    lines.iter()
+         .map(|l| format!("{}{}", prefix, l))
+         .collect::<Vec<_>>()
+         .connect(&format!("\n{}", indent))
+}
+
+fn rewrite_block_comment(orig: &str, offset: usize) -> String {
+    let mut lines = orig.lines();
+    let mut result = lines.next().unwrap().trim_right().to_owned();
+
+    let rest: Vec<&str> = lines.collect();
+    // Lines which don't start with `*` keep their indentation relative to
+    // each other.
+    let min_indent = rest.iter()
+                         .filter(|l| l.trim().len() > 0 && !l.trim_left().starts_with('*'))
+                         .map(|l| l.len() - l.trim_left().len())
+                         .min()
+                         .unwrap_or(0);
+
+    let indent = make_indent(offset);
+    for line in rest {
+        result.push('\n');
+        let trimmed = line.trim();
+        if trimmed.len() == 0 {
+            continue;
+        }
+        result.push_str(&indent);
+        if trimmed.starts_with('*') {
+            result.push(' ');
+            result.push_str(trimmed);
+        } else {
+            result.push_str("   ");
+            result.push_str(line[min_indent..].trim_right());
+        }
+    }
+
+    result
+}
+
+// Rewrite the comments in text, which should consist only of comments and
+// whitespace (i.e., the gaps between items or statements). Comments which
+// start a line are re-indented to indent; comments which follow code on the
+// same line are left alone. Returns None if text contains anything other than
+// comments and whitespace.
+pub fn rewrite_comments_in_whitespace(text: &str, indent: usize) -> Option<String> {
+    let mut result = String::with_capacity(text.len());
+    // Whitespace at the start of the current line, which we may replace.
+    let mut line_start: Option<usize> = None;
+    let mut pos = 0;
+
+    while pos < text.len() {
+        let rest = &text[pos..];
+        let c = rest.chars().next().unwrap();
+        if c == '\n' {
+            result.push_str(&text[line_start.unwrap_or(pos)..pos + 1]);
+            line_start = Some(pos + 1);
+            pos += 1;
+        } else if c.is_whitespace() {
+            if line_start.is_none() {
+                result.push(c);
+            }
+            pos += c.len_utf8();
+        } else if rest.starts_with("//") || rest.starts_with("/*") {
+            let len = match comment_len(rest) {
+                Some(len) => len,
+                None => return None,
+            };
+            let comment = &rest[..len];
+            match line_start {
+                Some(_) => {
+                    let width = config!(max_width).saturating_sub(indent);
+                    result.push_str(&make_indent(indent));
+                    result.push_str(&rewrite_comment(comment, width, indent));
+                }
+                None => result.push_str(comment),
+            }
+            line_start = None;
+            pos += len;
+        } else {
+            return None;
+        }
+    }
+    if let Some(start) = line_start {
+        result.push_str(&text[start..]);
+    }
+
+    Some(result)
+}
+
+// The position of the first occurrence of pat in text which is not inside a
+// comment.
+pub fn find_uncommented(text: &str, pat: &str) -> Option<usize> {
+    let mut pos = 0;
+    while pos < text.len() {
+        let rest = &text[pos..];
+        if rest.starts_with(pat) {
+            return Some(pos);
+        }
+        match comment_len(rest) {
+            Some(len) => pos += len,
+            None => pos += rest.chars().next().unwrap().len_utf8(),
+        }
+    }
+
+    None
+}
+
+// Does text contain a line comment (i.e., one which must be followed by a
+// newline)?
+pub fn contains_line_comment(text: &str) -> bool {
+    find_uncommented(text, "//").is_some()
+}
+
+// The length of the comment at the start of text, not including the newline
+// which ends a line comment. None if text does not start with a (complete)
+// comment.
+pub fn comment_len(text: &str) -> Option<usize> {
+    if text.starts_with("//") {
+        return Some(text.find('\n').unwrap_or(text.len()));
+    }
+    if !text.starts_with("/*") {
+        return None;
+    }
+
+    // Block comments nest.
+    let mut depth = 0;
+    let mut pos = 0;
+    while pos < text.len() {
+        let rest = &text[pos..];
+        if rest.starts_with("/*") {
+            depth += 1;
+            pos += 2;
+        } else if rest.starts_with("*/") {
+            depth -= 1;
+            pos += 2;
+            if depth == 0 {
+                return Some(pos);
+            }
+        } else {
+            pos += rest.chars().next().unwrap().len_utf8();
+        }
+    }
+
+    None
+}
+
+// Rewrite a run of doc comment attributes as a single doc comment in the given
+// style, formatting any Rust code blocks if format_code_in_doc_comments is set.
+// Returns None if any of attrs is not a doc comment, or if the documentation
+// can't be written in that style.
+pub fn rewrite_doc_comment(attrs: &[ast::Attribute],
+                           style: DocCommentStyle,
+                           indent: usize)
+                           -> Option<String> {
+    let inner = match attrs.first() {
+        Some(attr) => attr.node.style == ast::AttrStyle::AttrInner,
+        None => return None,
+    };
+
+    let mut lines = Vec::new();
+    for attr in attrs {
+        match doc_comment_lines(attr) {
+            Some(attr_lines) => lines.extend(attr_lines.into_iter()),
+            None => return None,
+        }
+    }
+
+    let mut style = style;
+    if config!(format_code_in_doc_comments) {
+        let formatted = format_doc_code(&lines, indent);
+        if style == DocCommentStyle::Preserve && formatted != lines {
+            // Keep the kind of comment we found, where we can.
+            style = if attrs.len() == 1 && is_block_doc_comment(&attrs[0]) {
+                DocCommentStyle::Block
+            } else {
+                DocCommentStyle::Line
+            };
+        }
+        lines = formatted;
+    }
+
+    let indent = make_indent(indent);
+    match style {
+        DocCommentStyle::Preserve => None,
+        DocCommentStyle::Line => {
+            let prefix = if inner { "//!" } else { "///" };
+            Some(lines.iter()
+                      .map(|l| format!("{}{}", prefix, l).trim_right().to_owned())
+                      .collect::<Vec<_>>()
+                      .connect(&format!("\n{}", indent)))
+        }
+        DocCommentStyle::Block => {
+            if lines.iter().any(|l| l.contains("*/") || l.contains("/*")) {
+                return None;
+            }
+
+            let mut result = if inner { "/*!".to_owned() } else { "/**".to_owned() };
+            for line in &lines {
+                result.push('\n');
+                result.push_str(&indent);
+                result.push_str(format!(" *{}", line).trim_right());
+            }
+            result.push('\n');
+            result.push_str(&indent);
+            result.push_str(" */");
+
+            Some(result)
+        }
+    }
+}
+
+// Format the Rust code blocks in the lines of a doc comment. Blocks which
+// don't parse, or which would be too wide once formatted, are left alone.
+fn format_doc_code(lines: &[String], indent: usize) -> Vec<String> {
+    let mut result = Vec::with_capacity(lines.len());
+    let mut i = 0;
+    while i < lines.len() {
+        let line = &lines[i];
+        result.push(line.clone());
+        i += 1;
+        if !is_rust_fence(line.trim()) {
+            continue;
+        }
+
+        let end = match lines[i..].iter().position(|l| l.trim().starts_with("```")) {
+            Some(n) => i + n,
+            None => continue,
+        };
+        let fence_indent = &line[..line.len() - line.trim_left().len()];
+        // 4 = "/// ".len()
+        let width = config!(max_width).saturating_sub(indent + 4);
+        match format_doc_test(&lines[i..end], fence_indent, width) {
+            Some(code) => result.extend(code.into_iter()),
+            None => result.extend(lines[i..end].iter().cloned()),
+        }
+        result.push(lines[end].clone());
+        i = end + 1;
+    }
+
+    result
+}
+
+// Is line the start of a fenced code block which rustdoc treats as Rust?
+fn is_rust_fence(line: &str) -> bool {
+    if !line.starts_with("```") {
+        return false;
+    }
+    let info = line[3..].trim();
+    let rust_tags = ["rust", "ignore", "no_run", "should_panic"];
+    info.len() == 0 || info.split(',').all(|t| rust_tags.contains(&t.trim()))
+}
+
+fn format_doc_test(code: &[String], fence_indent: &str, width: usize) -> Option<Vec<String>> {
+    let mut source = String::new();
+    for line in code {
+        if line.trim().len() > 0 {
+            if !line.starts_with(fence_indent) {
+                return None;
+            }
+            let line = &line[fence_indent.len()..];
+            // Lines hidden by rustdoc (`# ...`) would need special treatment.
+            if line.starts_with('#') && !line.starts_with("#[") {
+                return None;
+            }
+            source.push_str(line);
+        }
+        source.push('\n');
+    }
+
+    let formatted = match ::format_code(&source) {
+        Some(formatted) => formatted,
+        None => return None,
+    };
+
+    let mut result = Vec::new();
+    for line in formatted.lines() {
+        if fence_indent.len() + line.len() > width {
+            return None;
+        }
+        if line.len() == 0 {
+            result.push(String::new());
+        } else {
+            result.push(format!("{}{}", fence_indent, line));
+        }
+    }
+
+    Some(result)
+}
+
+// Is attr a doc comment (either sugared or a `#[doc = "..."]` attribute)?
+pub fn is_doc_comment(attr: &ast::Attribute) -> bool {
+    doc_comment_lines(attr).is_some()
+}
+
+fn is_block_doc_comment(attr: &ast::Attribute) -> bool {
+    match attr.node.value.node {
+        ast::MetaItem_::MetaNameValue(_, ref lit) => match lit.node {
+            ast::Lit_::LitStr(ref s, _) => attr.node.is_sugared_doc && s.starts_with("/*"),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// The lines of documentation in a doc comment attribute, as rustdoc will see
+// them, or None if attr is not a doc comment. Each non-empty line starts with
+// the space which usually follows the comment token.
+fn doc_comment_lines(attr: &ast::Attribute) -> Option<Vec<String>> {
+    let text = match attr.node.value.node {
+        ast::MetaItem_::MetaNameValue(ref name, ref lit) if &**name == "doc" => {
+            match lit.node {
+                ast::Lit_::LitStr(ref s, _) => s,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+
+    let lines: Vec<String> = if attr.node.is_sugared_doc {
+        comments::strip_doc_comment_decoration(text).lines().map(|l| l.to_owned()).collect()
+    } else {
+        text.lines()
+            .map(|l| if l.len() == 0 { String::new() } else { format!(" {}", l) })
+            .collect()
+    };
+
+    if lines.len() == 0 {
+        Some(vec![String::new()])
+    } else {
+        Some(lines)
+    }
+}
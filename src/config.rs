@@ -16,12 +16,35 @@ pub struct Config {
     pub ideal_width: usize,
     pub leeway: usize,
     pub tab_spaces: usize,
+    pub indent_style: ::IndentStyle,
     pub newline_style: ::NewlineStyle,
     pub fn_brace_style: ::BraceStyle,
+    pub item_brace_style: ::BraceStyle,
     pub fn_return_indent: ::ReturnIndent,
     pub fn_args_paren_newline: bool,
+    pub where_indent: ::BlockIndentStyle,
+    pub where_single_line: bool,
+    pub where_pred_tactic: ::lists::ListTactic,
+    pub where_trailing_comma: ::lists::SeparatorTactic,
+    pub where_brace_newline: bool,
     pub struct_trailing_comma: bool,
     pub struct_lit_trailing_comma: ::lists::SeparatorTactic,
+    pub enum_trailing_comma: bool,
+    pub wrap_comments: bool,
+    pub normalize_doc_comments: ::DocCommentStyle,
+    pub format_code_in_doc_comments: bool,
+    pub reorder_imports: bool,
+    pub group_imports: bool,
+    pub imports_granularity: ::ImportsGranularity,
+    pub report_glob_imports: bool,
+    pub remove_unused_imports: bool,
+    pub reorder_extern_crates: bool,
+    pub reorder_mods: bool,
+    pub struct_field_align: bool,
+    pub struct_lit_field_align: bool,
+    pub const_assign_align: bool,
+    pub let_assign_align: bool,
+    pub trailing_comment_align: bool,
 }
 
 impl Config {
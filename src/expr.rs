@@ -8,263 +8,586 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use visitor::FmtVisitor;
+use rewrite::{Rewrite, RewriteContext};
 use utils::*;
-use lists::{write_list, ListFormatting, SeparatorTactic, ListTactic};
+use lists::{write_list, itemize_list, visual_tactic, ListFormatting, SeparatorTactic, ListTactic,
+            ListLayout, LIST_LAYOUTS};
+use comment::find_uncommented;
+use types::rewrite_path;
+use macros::rewrite_macro;
 
 use syntax::{ast, ptr};
-use syntax::codemap::{Pos, Span};
+use syntax::codemap::{self, Pos, Span, BytePos};
 use syntax::parse::token;
-use syntax::print::pprust;
 
 use MIN_STRING;
 
-impl<'a> FmtVisitor<'a> {
-    // TODO NEEDS TESTS
-    fn rewrite_string_lit(&mut self, s: &str, span: Span, width: usize, offset: usize) -> String {
-        // FIXME I bet this stomps unicode escapes in the source string
-
-        // Check if there is anything to fix: we always try to fixup multi-line
-        // strings, or if the string is too long for the line.
-        let l_loc = self.codemap.lookup_char_pos(span.lo);
-        let r_loc = self.codemap.lookup_char_pos(span.hi);
-        if l_loc.line == r_loc.line && r_loc.col.to_usize() <= config!(max_width) {
-            return self.snippet(span);
+impl Rewrite for ast::Expr {
+    fn rewrite(&self, context: &RewriteContext, width: usize, offset: usize) -> Option<String> {
+        match self.node {
+            ast::Expr_::ExprLit(ref l) => {
+                match l.node {
+                    ast::Lit_::LitStr(ref is, _) => {
+                        let result = rewrite_string_lit(context, &is, l.span, width, offset);
+                        debug!("string lit: `{:?}`", result);
+                        return result;
+                    }
+                    _ => {}
+                }
+            }
+            ast::Expr_::ExprCall(ref callee, ref args) => {
+                return rewrite_call(context, callee, args, self.span, width, offset);
+            }
+            ast::Expr_::ExprParen(ref subexpr) => {
+                return rewrite_paren(context, subexpr, width, offset);
+            }
+            ast::Expr_::ExprStruct(ref path, ref fields, ref base) => {
+                return rewrite_struct_lit(context,
+                                          path,
+                                          fields,
+                                          base.as_ref().map(|e| &**e),
+                                          self.span,
+                                          width,
+                                          offset);
+            }
+            ast::Expr_::ExprTup(ref items) => {
+                return rewrite_tuple_lit(context, items, self.span, width, offset);
+            }
+            ast::Expr_::ExprVec(ref items) => {
+                return rewrite_array_lit(context, items, self.span, width, offset);
+            }
+            ast::Expr_::ExprRepeat(ref elem, ref count) => {
+                return rewrite_repeat(context, elem, count, width, offset);
+            }
+            ast::Expr_::ExprPath(ref qself, ref path) => {
+                return rewrite_path(context, qself.as_ref(), path, true, width, offset);
+            }
+            ast::Expr_::ExprMac(ref mac) => {
+                if let Some(result) = rewrite_macro(mac, context, width, offset) {
+                    return Some(result);
+                }
+            }
+            _ => {}
         }
 
-        // TODO if lo.col > IDEAL - 10, start a new line (need cur indent for that)
+        // We don't know how to format this expression, so leave it as it is,
+        // if that fits.
+        let snippet = context.snippet(self.span);
+        if fits_in(&snippet, width) {
+            Some(snippet)
+        } else {
+            None
+        }
+    }
+}
 
-        let s = s.escape_default();
+// TODO NEEDS TESTS
+fn rewrite_string_lit(context: &RewriteContext,
+                      s: &str,
+                      span: Span,
+                      width: usize,
+                      offset: usize)
+    -> Option<String>
+{
+    // FIXME I bet this stomps unicode escapes in the source string
 
-        let offset = offset + 1;
-        let indent = make_indent(offset);
-        let indent = &indent;
+    // Check if there is anything to fix: we always try to fixup multi-line
+    // strings, or if the string is too long for the line.
+    let l_loc = context.codemap.lookup_char_pos(span.lo);
+    let r_loc = context.codemap.lookup_char_pos(span.hi);
+    if l_loc.line == r_loc.line && r_loc.col.to_usize() <= config!(max_width) {
+        return Some(context.snippet(span));
+    }
 
-        let max_chars = width - 1;
+    // TODO if lo.col > IDEAL - 10, start a new line (need cur indent for that)
 
-        let mut cur_start = 0;
-        let mut result = String::new();
-        result.push('"');
-        loop {
-            let mut cur_end = cur_start + max_chars;
+    let s = s.escape_default();
 
-            if cur_end >= s.len() {
-                result.push_str(&s[cur_start..]);
-                break;
-            }
+    let offset = offset + 1;
+    let indent = make_indent(offset);
+    let indent = &indent;
+
+    let max_chars = try_opt!(width.checked_sub(1));
+    if max_chars < MIN_STRING {
+        return None;
+    }
+
+    let mut cur_start = 0;
+    let mut result = String::new();
+    result.push('"');
+    loop {
+        let mut cur_end = cur_start + max_chars;
 
-            // Make sure we're on a char boundary.
-            cur_end = next_char(&s, cur_end);
+        if cur_end >= s.len() {
+            result.push_str(&s[cur_start..]);
+            break;
+        }
 
-            // Push cur_end left until we reach whitespace
-            while !s.char_at(cur_end-1).is_whitespace() {
-                cur_end = prev_char(&s, cur_end);
+        // Make sure we're on a char boundary.
+        cur_end = next_char(&s, cur_end);
 
-                if cur_end - cur_start < MIN_STRING {
-                    // We can't break at whitespace, fall back to splitting
-                    // anywhere that doesn't break an escape sequence
-                    cur_end = next_char(&s, cur_start + max_chars);
-                    while s.char_at(cur_end) == '\\' {
-                        cur_end = prev_char(&s, cur_end);
-                    }
+        // Push cur_end left until we reach whitespace
+        while !s.char_at(cur_end-1).is_whitespace() {
+            cur_end = prev_char(&s, cur_end);
+
+            if cur_end - cur_start < MIN_STRING {
+                // We can't break at whitespace, fall back to splitting
+                // anywhere that doesn't break an escape sequence
+                cur_end = next_char(&s, cur_start + max_chars);
+                while s.char_at(cur_end) == '\\' {
+                    cur_end = prev_char(&s, cur_end);
                 }
             }
-            // Make sure there is no whitespace to the right of the break.
-            while cur_end < s.len() && s.char_at(cur_end).is_whitespace() {
-                cur_end = next_char(&s, cur_end+1);
-            }
-            result.push_str(&s[cur_start..cur_end]);
-            result.push_str("\\\n");
-            result.push_str(indent);
-
-            cur_start = cur_end;
         }
-        result.push('"');
+        // Make sure there is no whitespace to the right of the break.
+        while cur_end < s.len() && s.char_at(cur_end).is_whitespace() {
+            cur_end = next_char(&s, cur_end+1);
+        }
+        result.push_str(&s[cur_start..cur_end]);
+        result.push_str("\\\n");
+        result.push_str(indent);
 
-        result
+        cur_start = cur_end;
     }
+    result.push('"');
 
-    fn rewrite_call(&mut self,
-                    callee: &ast::Expr,
-                    args: &[ptr::P<ast::Expr>],
-                    width: usize,
-                    offset: usize)
-        -> String
-    {
-        debug!("rewrite_call, width: {}, offset: {}", width, offset);
-
-        // TODO using byte lens instead of char lens (and probably all over the place too)
-        let callee_str = self.rewrite_expr(callee, width, offset);
-        debug!("rewrite_call, callee_str: `{}`", callee_str);
+    Some(result)
+}
+
+fn rewrite_call(context: &RewriteContext,
+                callee: &ast::Expr,
+                args: &[ptr::P<ast::Expr>],
+                span: Span,
+                width: usize,
+                offset: usize)
+    -> Option<String>
+{
+    debug!("rewrite_call, width: {}, offset: {}", width, offset);
+
+    // TODO using byte lens instead of char lens (and probably all over the place too)
+    let callee_str = try_opt!(callee.rewrite(context, width, offset));
+    debug!("rewrite_call, callee_str: `{}`", callee_str);
+    let args_span = codemap::mk_sp(callee.span.hi, span.hi);
+    rewrite_call_inner(context, &callee_str, args, ("(", ")"), args_span, width, offset)
+}
+
+// Also used for list-like macro invocations, which is why the delimiters
+// are not always parens. span covers the delimiters and the arguments.
+// Returns None if the call doesn't fit in any layout.
+pub fn rewrite_call_inner(context: &RewriteContext,
+                          callee_str: &str,
+                          args: &[ptr::P<ast::Expr>],
+                          delims: (&str, &str),
+                          span: Span,
+                          width: usize,
+                          offset: usize)
+    -> Option<String>
+{
+    if args.len() == 0 {
+        return Some(format!("{}{}{}", callee_str, delims.0, delims.1));
+    }
+
+    for &layout in LIST_LAYOUTS {
+        let result = rewrite_call_args(context,
+                                       callee_str,
+                                       args,
+                                       delims,
+                                       span,
+                                       width,
+                                       offset,
+                                       layout);
+        match result {
+            Some(ref result) if fits_in(result, width) => return Some(result.clone()),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn rewrite_call_args(context: &RewriteContext,
+                     callee_str: &str,
+                     args: &[ptr::P<ast::Expr>],
+                     delims: (&str, &str),
+                     span: Span,
+                     width: usize,
+                     offset: usize,
+                     layout: ListLayout)
+    -> Option<String>
+{
+    let (tactic, indent, budget) = match layout {
         // 2 is for parens.
-        let remaining_width = width - callee_str.len() - 2;
-        let offset = callee_str.len() + 1 + offset;
-        let arg_count = args.len();
-
-        let args_str = if arg_count > 0 {
-            let args: Vec<_> = args.iter().map(|e| (self.rewrite_expr(e,
-                                                                      remaining_width,
-                                                                      offset), String::new())).collect();
-            // TODO move this into write_list
-            let tactics = if args.iter().any(|&(ref s, _)| s.contains('\n')) {
-                ListTactic::Vertical
+        ListLayout::Visual => (visual_tactic(),
+                               offset + callee_str.len() + 1,
+                               try_opt!(width.checked_sub(callee_str.len() + 2))),
+        ListLayout::Block | ListLayout::OnePerLine => {
+            let indent = context.block_indent + config!(tab_spaces);
+            let tactic = if layout == ListLayout::Block {
+                ListTactic::Horizontal
             } else {
-                ListTactic::HorizontalVertical
-            };
-            let fmt = ListFormatting {
-                tactic: tactics,
-                separator: ",",
-                trailing_separator: SeparatorTactic::Never,
-                indent: offset,
-                h_width: remaining_width,
-                v_width: remaining_width,
+                ListTactic::Vertical
             };
-            write_list(&args, &fmt)
-        } else {
-            String::new()
-        };
+            // 1 = `,`
+            (tactic, indent, try_opt!(config!(max_width).checked_sub(indent + 1)))
+        }
+    };
+    let inner_context = block_context(context, layout, indent);
 
-        format!("{}({})", callee_str, args_str)
+    let arg_strs = try_opt!(args.iter()
+                                .map(|e| e.rewrite(&inner_context, budget, indent))
+                                .collect::<Option<Vec<_>>>());
+    let mut items = itemize_list(context.codemap,
+                                 Vec::new(),
+                                 args.iter(),
+                                 ",",
+                                 delims.1,
+                                 |e| e.span.lo,
+                                 |e| e.span.hi,
+                                 |_| String::new(),
+                                 span_after(span, delims.0, context.codemap),
+                                 span.hi);
+    for (item, s) in items.iter_mut().zip(arg_strs.into_iter()) {
+        item.item = s;
     }
+    let fmt = ListFormatting {
+        tactic: tactic,
+        separator: ",",
+        trailing_separator: SeparatorTactic::Never,
+        indent: indent,
+        h_width: budget,
+        v_width: budget,
+        align_after: None,
+        align_comments: config!(trailing_comment_align),
+    };
+    let args_str = write_list(&items, &fmt);
 
-    fn rewrite_paren(&mut self, subexpr: &ast::Expr, width: usize, offset: usize) -> String {
-        debug!("rewrite_paren, width: {}, offset: {}", width, offset);
-        // 1 is for opening paren, 2 is for opening+closing, we want to keep the closing
-        // paren on the same line as the subexpr
-        let subexpr_str = self.rewrite_expr(subexpr, width-2, offset+1);
-        debug!("rewrite_paren, subexpr_str: `{}`", subexpr_str);
-        format!("({})", subexpr_str)
+    Some(wrap_list(context, callee_str, delims, &args_str, layout, indent))
+}
+
+// The context for the items of a list in the given layout. In the block
+// layouts, the items form a new block at indent.
+pub fn block_context<'a>(context: &RewriteContext<'a>, layout: ListLayout, indent: usize)
+    -> RewriteContext<'a>
+{
+    let block_indent = match layout {
+        ListLayout::Visual => context.block_indent,
+        ListLayout::Block | ListLayout::OnePerLine => indent,
+    };
+    RewriteContext { block_indent: block_indent, ..*context }
+}
+
+// Put list_str, which has been written at indent in the given layout, between
+// prefix and the delimiters.
+pub fn wrap_list(context: &RewriteContext,
+                 prefix: &str,
+                 delims: (&str, &str),
+                 list_str: &str,
+                 layout: ListLayout,
+                 indent: usize)
+    -> String
+{
+    match layout {
+        ListLayout::Visual => format!("{}{}{}{}", prefix, delims.0, list_str, delims.1),
+        ListLayout::Block | ListLayout::OnePerLine => {
+            format!("{}{}\n{}{}\n{}{}",
+                    prefix,
+                    delims.0.trim_right(),
+                    make_indent(indent),
+                    list_str,
+                    make_indent(context.block_indent),
+                    delims.1.trim_left())
+        }
     }
+}
 
-    fn rewrite_struct_lit(&mut self,
-                          path: &ast::Path,
-                          fields: &[ast::Field],
-                          base: Option<&ast::Expr>,
-                          width: usize,
-                          offset: usize)
-        -> String
-    {
-        debug!("rewrite_struct_lit: width {}, offset {}", width, offset);
-        assert!(fields.len() > 0 || base.is_some());
+fn rewrite_paren(context: &RewriteContext,
+                 subexpr: &ast::Expr,
+                 width: usize,
+                 offset: usize)
+    -> Option<String>
+{
+    debug!("rewrite_paren, width: {}, offset: {}", width, offset);
+    // 1 is for opening paren, 2 is for opening+closing, we want to keep the closing
+    // paren on the same line as the subexpr
+    let budget = try_opt!(width.checked_sub(2));
+    let subexpr_str = try_opt!(subexpr.rewrite(context, budget, offset + 1));
+    debug!("rewrite_paren, subexpr_str: `{}`", subexpr_str);
+    Some(format!("({})", subexpr_str))
+}
 
-        let path_str = pprust::path_to_string(path);
+fn rewrite_struct_lit(context: &RewriteContext,
+                      path: &ast::Path,
+                      fields: &[ast::Field],
+                      base: Option<&ast::Expr>,
+                      span: Span,
+                      width: usize,
+                      offset: usize)
+    -> Option<String>
+{
+    debug!("rewrite_struct_lit: width {}, offset {}", width, offset);
+    assert!(fields.len() > 0 || base.is_some());
+
+    let path_str = try_opt!(rewrite_path(context, None, path, true, width, offset));
+
+    // Several fields on a line of their own would look odd, so we go straight
+    // from the visual layout to one field per line.
+    for &layout in &[ListLayout::Visual, ListLayout::OnePerLine] {
+        let result = rewrite_struct_lit_fields(context,
+                                               &path_str,
+                                               fields,
+                                               base,
+                                               span,
+                                               width,
+                                               offset,
+                                               layout);
+        match result {
+            Some(ref result) if fits_in(result, width) => return Some(result.clone()),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn rewrite_struct_lit_fields(context: &RewriteContext,
+                             path_str: &str,
+                             fields: &[ast::Field],
+                             base: Option<&ast::Expr>,
+                             span: Span,
+                             width: usize,
+                             offset: usize,
+                             layout: ListLayout)
+    -> Option<String>
+{
+    let (tactic, indent, budget) = match layout {
         // Foo { a: Foo } - indent is +3, width is -5.
-        let indent = offset + path_str.len() + 3;
-        let budget = width - (path_str.len() + 5);
-
-        let mut field_strs: Vec<_> =
-            fields.iter().map(|f| self.rewrite_field(f, budget, indent)).collect();
-        if let Some(expr) = base {
-            // Another 2 on the width/indent for the ..
-            field_strs.push(format!("..{}", self.rewrite_expr(expr, budget - 2, indent + 2)))
+        ListLayout::Visual => (visual_tactic(),
+                               offset + path_str.len() + 3,
+                               try_opt!(width.checked_sub(path_str.len() + 5))),
+        ListLayout::Block | ListLayout::OnePerLine => {
+            let indent = context.block_indent + config!(tab_spaces);
+            // 1 = `,`
+            (ListTactic::Vertical, indent, try_opt!(config!(max_width).checked_sub(indent + 1)))
         }
+    };
+    let inner_context = block_context(context, layout, indent);
 
-        // FIXME comments
-        let field_strs: Vec<_> = field_strs.into_iter().map(|s| (s, String::new())).collect();
-        let tactics = if field_strs.iter().any(|&(ref s, _)| s.contains('\n')) {
-            ListTactic::Vertical
-        } else {
-            ListTactic::HorizontalVertical
-        };
-        let fmt = ListFormatting {
-            tactic: tactics,
-            separator: ",",
-            trailing_separator: if base.is_some() {
-                    SeparatorTactic::Never
-                } else {
-                    config!(struct_lit_trailing_comma)
-                },
-            indent: indent,
-            h_width: budget,
-            v_width: budget,
-        };
-        let fields_str = write_list(&field_strs, &fmt);
-        format!("{} {{ {} }}", path_str, fields_str)
-
-        // FIXME if the usual multi-line layout is too wide, we should fall back to
-        // Foo {
-        //     a: ...,
-        // }
+    let mut field_strs = try_opt!(fields.iter()
+                                        .map(|f| {
+                                            rewrite_field(&inner_context, f, budget, indent)
+                                        })
+                                        .collect::<Option<Vec<_>>>());
+    if let Some(expr) = base {
+        // Another 2 on the width/indent for the ..
+        let expr_budget = try_opt!(budget.checked_sub(2));
+        let expr_str = try_opt!(expr.rewrite(&inner_context, expr_budget, indent + 2));
+        field_strs.push(format!("..{}", expr_str))
     }
 
-    fn rewrite_field(&mut self, field: &ast::Field, width: usize, offset: usize) -> String {
-        let name = &token::get_ident(field.ident.node);
-        let overhead = name.len() + 2;
-        let expr = self.rewrite_expr(&field.expr, width - overhead, offset + overhead);
-        format!("{}: {}", name, expr)
+    // The spans of the fields and of `..base`.
+    let mut spans: Vec<_> = fields.iter().map(|f| f.span).collect();
+    if let Some(expr) = base {
+        let prev_hi = fields.last().map_or(span.lo, |f| f.span.hi);
+        let snippet = context.snippet(codemap::mk_sp(prev_hi, expr.span.lo));
+        let dots_pos = find_uncommented(&snippet, "..").unwrap();
+        spans.push(codemap::mk_sp(prev_hi + BytePos(dots_pos as u32), expr.span.hi));
+    }
+    let mut items = itemize_list(context.codemap,
+                                 Vec::new(),
+                                 spans.iter(),
+                                 ",",
+                                 "}",
+                                 |sp| sp.lo,
+                                 |sp| sp.hi,
+                                 |_| String::new(),
+                                 span_after(span, "{", context.codemap),
+                                 span.hi);
+    for (item, s) in items.iter_mut().zip(field_strs.into_iter()) {
+        item.item = s;
     }
 
-    fn rewrite_tuple_lit(&mut self, items: &[ptr::P<ast::Expr>], width: usize, offset: usize)
-        -> String {
-        // opening paren
-        let indent = offset + 1;
-        // In case of length 1, need a trailing comma
-        if items.len() == 1 {
-            return format!("({},)", self.rewrite_expr(&*items[0], width - 3, indent));
-        }
-        // Only last line has width-1 as budget, other may take max_width
-        let item_strs: Vec<_> =
-            items.iter()
-                 .enumerate()
-                 .map(|(i, item)| self.rewrite_expr(
-                    item,
-                    // last line : given width (minus "("+")"), other lines : max_width
-                    // (minus "("+","))
-                    if i == items.len() - 1 { width - 2 } else { config!(max_width) - indent - 2 },
-                    indent))
-                 .collect();
-        let tactics = if item_strs.iter().any(|s| s.contains('\n')) {
-            ListTactic::Vertical
+    let fmt = ListFormatting {
+        tactic: tactic,
+        separator: ",",
+        trailing_separator: if base.is_some() {
+                SeparatorTactic::Never
+            } else {
+                config!(struct_lit_trailing_comma)
+            },
+        indent: indent,
+        h_width: budget,
+        v_width: budget,
+        align_after: if config!(struct_lit_field_align) {
+            Some(": ")
         } else {
-            ListTactic::HorizontalVertical
-        };
-        // FIXME handle comments
-        let item_strs: Vec<_> = item_strs.into_iter().map(|s| (s, String::new())).collect();
-        let fmt = ListFormatting {
-            tactic: tactics,
-            separator: ",",
-            trailing_separator: SeparatorTactic::Never,
-            indent: indent,
-            h_width: width - 2,
-            v_width: width - 2,
-        };
-        let item_str = write_list(&item_strs, &fmt);
-        format!("({})", item_str)
+            None
+        },
+        align_comments: config!(trailing_comment_align),
+    };
+    let fields_str = write_list(&items, &fmt);
+
+    Some(wrap_list(context, &format!("{} ", path_str), ("{ ", " }"), &fields_str, layout, indent))
+}
+
+fn rewrite_field(context: &RewriteContext,
+                 field: &ast::Field,
+                 width: usize,
+                 offset: usize)
+    -> Option<String>
+{
+    let name = &token::get_ident(field.ident.node);
+    let overhead = name.len() + 2;
+    let expr = try_opt!(field.expr.rewrite(context,
+                                           try_opt!(width.checked_sub(overhead)),
+                                           offset + overhead));
+    Some(format!("{}: {}", name, expr))
+}
+
+fn rewrite_tuple_lit(context: &RewriteContext,
+                     items: &[ptr::P<ast::Expr>],
+                     span: Span,
+                     width: usize,
+                     offset: usize)
+    -> Option<String>
+{
+    // opening paren
+    let indent = offset + 1;
+    // In case of length 1, need a trailing comma
+    if items.len() == 1 {
+        let budget = try_opt!(width.checked_sub(3));
+        return Some(format!("({},)", try_opt!(items[0].rewrite(context, budget, indent))));
+    }
+    // 2 = `(` and `)`
+    let budget = try_opt!(width.checked_sub(2));
+    // Only last line has width-1 as budget, other may take max_width
+    let item_strs = try_opt!(items.iter()
+                                  .enumerate()
+                                  .map(|(i, item)| {
+                                      // last line : given width (minus "("+")"), other
+                                      // lines : max_width (minus "("+","))
+                                      let item_width = if i == items.len() - 1 {
+                                          budget
+                                      } else {
+                                          config!(max_width).saturating_sub(indent + 2)
+                                      };
+                                      item.rewrite(context, item_width, indent)
+                                  })
+                                  .collect::<Option<Vec<_>>>());
+    let mut list_items = itemize_list(context.codemap,
+                                      Vec::new(),
+                                      items.iter(),
+                                      ",",
+                                      ")",
+                                      |item| item.span.lo,
+                                      |item| item.span.hi,
+                                      |_| String::new(),
+                                      span_after(span, "(", context.codemap),
+                                      span.hi);
+    for (list_item, s) in list_items.iter_mut().zip(item_strs.into_iter()) {
+        list_item.item = s;
     }
+    let fmt = ListFormatting {
+        tactic: ListTactic::HorizontalVertical,
+        separator: ",",
+        trailing_separator: SeparatorTactic::Never,
+        indent: indent,
+        h_width: budget,
+        v_width: budget,
+        align_after: None,
+        align_comments: config!(trailing_comment_align),
+    };
+    let item_str = write_list(&list_items, &fmt);
+    Some(format!("({})", item_str))
+}
 
+fn rewrite_array_lit(context: &RewriteContext,
+                     items: &[ptr::P<ast::Expr>],
+                     span: Span,
+                     width: usize,
+                     offset: usize)
+    -> Option<String>
+{
+    if items.len() == 0 {
+        return Some("[]".to_owned());
+    }
 
-    pub fn rewrite_expr(&mut self, expr: &ast::Expr, width: usize, offset: usize) -> String {
-        match expr.node {
-            ast::Expr_::ExprLit(ref l) => {
-                match l.node {
-                    ast::Lit_::LitStr(ref is, _) => {
-                        let result = self.rewrite_string_lit(&is, l.span, width, offset);
-                        debug!("string lit: `{}`", result);
-                        return result;
-                    }
-                    _ => {}
-                }
-            }
-            ast::Expr_::ExprCall(ref callee, ref args) => {
-                return self.rewrite_call(callee, args, width, offset);
-            }
-            ast::Expr_::ExprParen(ref subexpr) => {
-                return self.rewrite_paren(subexpr, width, offset);
-            }
-            ast::Expr_::ExprStruct(ref path, ref fields, ref base) => {
-                return self.rewrite_struct_lit(path,
-                                               fields,
-                                               base.as_ref().map(|e| &**e),
-                                               width,
-                                               offset);
-            }
-            ast::Expr_::ExprTup(ref items) => {
-                return self.rewrite_tuple_lit(items, width, offset);
-            }
-            _ => {}
+    // 2 = `[` + `]`
+    let budget = try_opt!(width.checked_sub(2));
+    let indent = offset + 1;
+    let item_strs = try_opt!(items.iter()
+                                  .map(|item| item.rewrite(context, budget, indent))
+                                  .collect::<Option<Vec<_>>>());
+    let mut list_items = itemize_list(context.codemap,
+                                      Vec::new(),
+                                      items.iter(),
+                                      ",",
+                                      "]",
+                                      |item| item.span.lo,
+                                      |item| item.span.hi,
+                                      |_| String::new(),
+                                      span_after(span, "[", context.codemap),
+                                      span.hi);
+    for (list_item, s) in list_items.iter_mut().zip(item_strs.into_iter()) {
+        list_item.item = s;
+    }
+
+    // Tables of numbers which need several lines are laid out in columns,
+    // by padding each number to the same width.
+    let one_line_width = list_items.iter()
+                                   .map(|item| item.item.len() + 2)
+                                   .fold(0, |a, l| a + l);
+    let has_comments = list_items.iter().any(|item| {
+        item.pre_comment.is_some() || item.post_comment.is_some()
+    });
+    if one_line_width > budget + 2 && !has_comments && items.iter().all(|e| is_number(e)) {
+        let column_width = list_items.iter().map(|item| item.item.len()).max().unwrap();
+        for list_item in &mut list_items {
+            let padding = make_indent(column_width - list_item.item.len());
+            list_item.item = format!("{}{}", padding, list_item.item);
         }
+    }
+
+    let fmt = ListFormatting {
+        tactic: ListTactic::Mixed,
+        separator: ",",
+        trailing_separator: SeparatorTactic::Never,
+        indent: indent,
+        h_width: budget,
+        v_width: budget,
+        align_after: None,
+        align_comments: config!(trailing_comment_align),
+    };
+    Some(format!("[{}]", write_list(&list_items, &fmt)))
+}
 
-        let result = self.snippet(expr.span);
-        result
+// E.g., `[0u8; 256]`.
+fn rewrite_repeat(context: &RewriteContext,
+                  expr: &ast::Expr,
+                  count: &ast::Expr,
+                  width: usize,
+                  offset: usize)
+    -> Option<String>
+{
+    // 1 = `[`
+    let expr_str = try_opt!(expr.rewrite(context, try_opt!(width.checked_sub(1)), offset + 1));
+    // 2 = `; `
+    let used_width = last_line_offset(&expr_str, offset + 1) + 2;
+    // 1 = `]`
+    let budget = try_opt!((offset + width).checked_sub(used_width + 1));
+    let count_str = try_opt!(count.rewrite(context, budget, used_width));
+    Some(format!("[{}; {}]", expr_str, count_str))
+}
+
+// Is expr a (possibly negated) numeric literal?
+fn is_number(expr: &ast::Expr) -> bool {
+    match expr.node {
+        ast::Expr_::ExprLit(ref lit) => match lit.node {
+            ast::Lit_::LitInt(..) | ast::Lit_::LitFloat(..) | ast::Lit_::LitFloatUnsuffixed(..) => {
+                true
+            }
+            _ => false,
+        },
+        ast::Expr_::ExprUnary(ast::UnOp::UnNeg, ref operand) => is_number(operand),
+        _ => false,
     }
 }
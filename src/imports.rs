@@ -9,15 +9,27 @@
 // except according to those terms.
 
 use visitor::FmtVisitor;
-use lists::{write_list, ListFormatting, SeparatorTactic, ListTactic};
+use rewrite::Rewrite;
+use lists::{write_list, itemize_list, ListItem, ListFormatting, SeparatorTactic, ListTactic};
+use utils::{make_indent, span_after};
+use comment::{comment_len, rewrite_comments_in_whitespace};
 
 use syntax::ast;
+use syntax::attr::AttrMetaMethods;
+use syntax::codemap::{self, Span, BytePos, Pos};
 use syntax::parse::token;
-use syntax::print::pprust;
+use syntax::ptr::P;
+use syntax::visit::{self, Visitor};
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::mem;
+
+use ImportsGranularity;
 
 
 // TODO remove empty lists (if they're even possible)
-// TODO (some day) remove unused imports, expand globs, compress many single imports into a list import
+// TODO (some day) expand globs
 
 fn rewrite_single_use_list(path_str: String, vpi: ast::PathListItem, vis: &str) -> String {
     if let ast::PathListItem_::PathListIdent{ name, .. } = vpi.node {
@@ -39,6 +51,470 @@ fn rewrite_single_use_list(path_str: String, vpi: ast::PathListItem, vis: &str)
 }
 
 impl<'a> FmtVisitor<'a> {
+    // Visit the items of a module. If reorder_imports is set, each contiguous
+    // run of use items is sorted. Blank lines and comments on their own line
+    // separate runs. If group_imports is set, blank lines do not separate runs,
+    // instead each run is split into groups for std, external crates and local
+    // modules. Imports within a run are merged or split according to
+    // imports_granularity. Runs of extern crates and of out-of-line mods are
+    // sorted if reorder_extern_crates and reorder_mods are set. If
+    // const_assign_align is set, the `=`s of runs of const and static items are
+    // lined up.
+    pub fn visit_mod_items(&mut self, items: &[P<ast::Item>]) {
+        let outer_module_names = mem::replace(&mut self.module_names, module_names(items));
+        // Uses may come before the extern crates they refer to, so find all
+        // the module's extern crates before grouping any imports. A nested
+        // module also sees the extern crates of its parents.
+        let mut extern_crates = self.extern_crates.clone();
+        for item in items {
+            if let ast::Item_::ItemExternCrate(_) = item.node {
+                let name = token::get_ident(item.ident).to_string();
+                if !extern_crates.contains(&name) {
+                    extern_crates.push(name);
+                }
+            }
+        }
+        let outer_extern_crates = mem::replace(&mut self.extern_crates, extern_crates);
+
+        let mut i = 0;
+        // The end of the current run of const and static items.
+        let mut assign_run_end = 0;
+        while i < items.len() {
+            if i >= assign_run_end {
+                self.assign_width = 0;
+                if config!(const_assign_align) && is_static_item(&items[i]) {
+                    assign_run_end = self.item_run_end(items,
+                                                       i,
+                                                       |item| is_static_item(item),
+                                                       false);
+                    self.assign_width = self.static_assign_width(&items[i..assign_run_end]);
+                }
+            }
+
+            let kind = self.reorder_kind(&items[i]);
+            let run_end = match kind {
+                Some(kind) => self.run_end(items, i, kind),
+                None => i + 1,
+            };
+
+            let reordered = match kind {
+                Some(RunKind::Use) => {
+                    // A single use item may still need splitting.
+                    let split = imports_granularity() == ImportsGranularity::Item;
+                    (run_end - i > 1 || split) && self.reorder_use_items(&items[i..run_end])
+                }
+                Some(_) => run_end - i > 1 && self.reorder_decl_items(&items[i..run_end]),
+                None => false,
+            };
+            if reordered {
+                i = run_end;
+                continue;
+            }
+
+            for item in &items[i..run_end] {
+                self.visit_item(item);
+            }
+            i = run_end;
+        }
+        self.assign_width = 0;
+        self.module_names = outer_module_names;
+        self.extern_crates = outer_extern_crates;
+    }
+
+    // Rewrite a use item, or None if it should be left as it is.
+    pub fn rewrite_use(&mut self,
+                       vp: &ast::ViewPath,
+                       vis: ast::Visibility,
+                       span: Span)
+                       -> Option<String> {
+        match vp.node {
+            ast::ViewPath_::ViewPathList(ref path, ref path_list) => {
+                let block_indent = self.block_indent;
+                let one_line_budget = config!(max_width) - block_indent;
+                let multi_line_budget = config!(ideal_width) - block_indent;
+                Some(self.rewrite_use_list(block_indent,
+                                           one_line_budget,
+                                           multi_line_budget,
+                                           path,
+                                           path_list,
+                                           vis,
+                                           span))
+            }
+            ast::ViewPath_::ViewPathGlob(ref path) => {
+                if config!(report_glob_imports) {
+                    let loc = self.codemap.lookup_char_pos(span.lo);
+                    self.glob_imports.push((loc.file.name.clone(), loc.line));
+                }
+                // 4 = "::*;"
+                Some(format!("{}::*;", self.rewrite_use_path(path, vis, 4)))
+            }
+            ast::ViewPath_::ViewPathSimple(ident, ref path) => {
+                let ident_str = token::get_ident(ident).to_string();
+                let renamed = path.segments.last().map_or(true, |segment| {
+                    token::get_ident(segment.identifier).to_string() != ident_str
+                });
+                if renamed {
+                    // 5 = " as " + ";"
+                    let path_str = self.rewrite_use_path(path, vis, ident_str.len() + 5);
+                    Some(format!("{} as {};", path_str, ident_str))
+                } else {
+                    Some(format!("{};", self.rewrite_use_path(path, vis, 1)))
+                }
+            }
+        }
+    }
+
+    fn is_unused_import(&self, span: Span) -> bool {
+        self.unused_imports.iter().any(|unused| *unused == span)
+    }
+
+    // Are all the imports of a use item unused?
+    pub fn is_unused_use(&self, vp: &ast::ViewPath) -> bool {
+        if self.unused_imports.len() == 0 {
+            return false;
+        }
+        match vp.node {
+            ast::ViewPath_::ViewPathList(_, ref path_list) => {
+                path_list.iter().all(|vpi| self.is_unused_import(vpi.span))
+            }
+            _ => self.is_unused_import(vp.span),
+        }
+    }
+
+    // Remove a use item (with its attributes), and its line if nothing else is
+    // on it.
+    pub fn remove_use_item(&mut self, item: &ast::Item) {
+        let lo = item_lo(item);
+        let before = self.snippet(codemap::mk_sp(self.last_pos, lo));
+        let line_start = match before.rfind('\n') {
+            Some(i) if before[i + 1..].trim().len() == 0 => {
+                self.last_pos + BytePos(i as u32 + 1)
+            }
+            _ => lo,
+        };
+        self.format_missing(line_start);
+        self.last_pos = item.span.hi;
+
+        // If only whitespace follows the item, remove that and the newline.
+        let loc = self.codemap.lookup_char_pos(item.span.hi);
+        if let Some(line) = loc.file.get_line(loc.line - 1) {
+            let start = line.char_indices()
+                            .nth(loc.col.to_usize())
+                            .map_or(line.len(), |(i, _)| i);
+            if line_start != lo && line[start..].trim().len() == 0 {
+                self.last_pos = item.span.hi + BytePos((line.len() - start) as u32 + 1);
+            }
+        }
+    }
+
+    // Write `use path` with its visibility, but without the `;`. suffix_len is
+    // the width of the text which will follow the path.
+    fn rewrite_use_path(&self,
+                        path: &ast::Path,
+                        vis: ast::Visibility,
+                        suffix_len: usize)
+                        -> String {
+        let vis = vis_str(vis);
+        // 4 = "use "
+        let offset = self.block_indent + vis.len() + 4;
+        let budget = config!(max_width).saturating_sub(offset + suffix_len);
+        let path_str = path.rewrite(&self.get_context(), budget, offset)
+                           .unwrap_or_else(|| self.snippet(path.span));
+        format!("{}use {}", vis, path_str)
+    }
+
+    // The kind of run item belongs to, or None if it is not reordered.
+    fn reorder_kind(&self, item: &ast::Item) -> Option<RunKind> {
+        match item.node {
+            ast::Item_::ItemUse(_) if config!(reorder_imports) || config!(group_imports) ||
+                                      imports_granularity() != ImportsGranularity::Preserve => {
+                Some(RunKind::Use)
+            }
+            ast::Item_::ItemExternCrate(_) if config!(reorder_extern_crates) => {
+                Some(RunKind::ExternCrate)
+            }
+            ast::Item_::ItemMod(_) if config!(reorder_mods) && self.is_mod_decl(item) => {
+                Some(RunKind::ModDecl)
+            }
+            _ => None,
+        }
+    }
+
+    // The end of the run of items of the given kind starting at start.
+    fn run_end(&self, items: &[P<ast::Item>], start: usize, kind: RunKind) -> usize {
+        let grouped = kind == RunKind::Use && config!(group_imports);
+        self.item_run_end(items, start, |item| self.reorder_kind(item) == Some(kind), grouped)
+    }
+
+    // The end of the run of items for which in_run is true, starting at start
+    // (which is assumed to be in the run).
+    fn item_run_end<F>(&self,
+                       items: &[P<ast::Item>],
+                       start: usize,
+                       in_run: F,
+                       allow_blank_lines: bool)
+                       -> usize
+        where F: Fn(&ast::Item) -> bool
+    {
+        let mut end = start + 1;
+        while end < items.len() && in_run(&items[end]) {
+            let gap = self.snippet(codemap::mk_sp(items[end - 1].span.hi,
+                                                  item_lo(&items[end])));
+            // A comment may follow the previous item on the same line, but
+            // otherwise there must be only a single newline.
+            let (same_line, rest) = match gap.find('\n') {
+                Some(i) => (&gap[..i], &gap[i + 1..]),
+                None => (&gap[..], ""),
+            };
+            let blank_line = rest.contains('\n') && !allow_blank_lines;
+            if rewrite_comments_in_whitespace(same_line, 0).is_none() ||
+               rest.trim().len() > 0 || blank_line {
+                break;
+            }
+            end += 1;
+        }
+
+        end
+    }
+
+    // Sort a run of extern crates or out-of-line mods by name, keeping
+    // attributes and comments on the same line with their item. Returns false
+    // (and writes nothing) if the items can't be reordered.
+    fn reorder_decl_items(&mut self, items: &[P<ast::Item>]) -> bool {
+        let mut entries = Vec::with_capacity(items.len());
+        for item in items {
+            let attrs = match self.run_item_attrs(item) {
+                Some(attrs) => attrs,
+                // Skipped item.
+                None => return false,
+            };
+
+            let (name, mut text) = match item.node {
+                ast::Item_::ItemExternCrate(orig_name) => {
+                    let name = match orig_name {
+                        Some(name) => token::get_name(name).to_string(),
+                        None => token::get_ident(item.ident).to_string(),
+                    };
+                    (name, self.rewrite_extern_crate(item))
+                }
+                ast::Item_::ItemMod(_) => {
+                    (token::get_ident(item.ident).to_string(), rewrite_mod_decl(item))
+                }
+                _ => return false,
+            };
+
+            if let Some(comment) = self.trailing_comment(item) {
+                text.push(' ');
+                text.push_str(&comment);
+            }
+            entries.push(RunEntry {
+                key: name,
+                attrs: attrs,
+                text: text,
+            });
+        }
+
+        self.write_sorted_run(items, entries, |_, _| true);
+        true
+    }
+
+    pub fn rewrite_extern_crate(&self, item: &ast::Item) -> String {
+        let vis = vis_str(item.vis);
+        let ident = token::get_ident(item.ident);
+        match item.node {
+            ast::Item_::ItemExternCrate(Some(orig_name)) => {
+                format!("{}extern crate {} as {};", vis, token::get_name(orig_name), ident)
+            }
+            _ => format!("{}extern crate {};", vis, ident),
+        }
+    }
+
+    // Is item a mod declared here but defined in another file (`mod foo;`)?
+    pub fn is_mod_decl(&self, item: &ast::Item) -> bool {
+        match item.node {
+            ast::Item_::ItemMod(ref m) => {
+                self.codemap.lookup_char_pos(item.span.lo).file.name !=
+                self.codemap.lookup_char_pos(m.inner.lo).file.name
+            }
+            _ => false,
+        }
+    }
+
+    // Sort, group, merge and/or split a run of use items, keeping attributes
+    // and comments on the same line with their item. Returns false (and writes
+    // nothing) if the items can't be reordered.
+    fn reorder_use_items(&mut self, items: &[P<ast::Item>]) -> bool {
+        let granularity = imports_granularity();
+
+        // Entries are keyed by the index of the first item contributing to
+        // them, so that merged imports keep their place when the uses are not
+        // sorted.
+        let mut entries = Vec::with_capacity(items.len());
+        let mut imports = Vec::new();
+        for (index, item) in items.iter().enumerate() {
+            if let ast::Item_::ItemUse(ref vp) = item.node {
+                if self.is_unused_use(vp) {
+                    continue;
+                }
+            }
+
+            let attrs = match self.run_item_attrs(item) {
+                Some(attrs) => attrs,
+                // Skipped item.
+                None => return false,
+            };
+
+            let vp = match item.node {
+                ast::Item_::ItemUse(ref vp) => vp,
+                _ => return false,
+            };
+
+            // Uses with a trailing comment are left whole, so the comment
+            // stays with its imports.
+            let comment = self.trailing_comment(item);
+            if granularity != ImportsGranularity::Preserve && comment.is_none() {
+                if let Some(flat) = flatten_use(vp,
+                                                vis_str(item.vis),
+                                                &attrs,
+                                                &self.unused_imports) {
+                    imports.extend(flat.into_iter().map(|import| (index, import)));
+                    continue;
+                }
+            }
+
+            let mut use_str = match self.rewrite_use(vp, item.vis, item.span) {
+                Some(use_str) => use_str,
+                None => self.snippet(item.span),
+            };
+            let group = self.import_group(&view_path_segments(vp));
+            let key = use_sort_key(&use_str);
+            if let Some(comment) = comment {
+                use_str.push(' ');
+                use_str.push_str(&comment);
+            }
+            entries.push(RunEntry {
+                key: (group, key, index),
+                attrs: attrs,
+                text: use_str,
+            });
+        }
+
+        if entries.len() == 0 && imports.len() == 0 {
+            // Every use is removed, leave that to visit_item.
+            return false;
+        }
+
+        for (index, import, use_str) in self.combine_imports(&imports, granularity) {
+            entries.push(RunEntry {
+                key: (self.import_group(&import.path), use_sort_key(&use_str), index),
+                attrs: import.attrs.clone(),
+                text: use_str,
+            });
+        }
+
+        // Without reorder_imports, uses stay in source order within a group.
+        if !config!(reorder_imports) {
+            for entry in &mut entries {
+                entry.key.1 = String::new();
+            }
+        }
+
+        self.write_sorted_run(items, entries, |a, b| a.0 == b.0);
+        true
+    }
+
+    // The rewritten outer attributes of an item in a reordered run, or None
+    // if the item is skipped.
+    fn run_item_attrs(&self, item: &ast::Item) -> Option<String> {
+        let attrs = outer_attrs(item);
+        if attrs.len() == 0 {
+            return Some(String::new());
+        }
+        self.rewrite_attrs(&attrs, self.block_indent)
+    }
+
+    // The comment following item on the same line, if there is one.
+    fn trailing_comment(&self, item: &ast::Item) -> Option<String> {
+        let comment_len = self.trailing_comment_len(item.span.hi);
+        if comment_len == 0 {
+            return None;
+        }
+        let comment_span = codemap::mk_sp(item.span.hi,
+                                          item.span.hi + BytePos(comment_len as u32));
+        Some(self.snippet(comment_span).trim().to_owned())
+    }
+
+    // Sort the entries for a run of items by key and write them in place of
+    // the items. Entries which are not in the same group are separated by a
+    // blank line. The sort is stable.
+    fn write_sorted_run<K, F>(&mut self,
+                              items: &[P<ast::Item>],
+                              mut entries: Vec<RunEntry<K>>,
+                              same_group: F)
+        where K: Ord,
+              F: Fn(&K, &K) -> bool
+    {
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+
+        let indent = make_indent(self.block_indent);
+        let mut result = String::new();
+        for (i, entry) in entries.iter().enumerate() {
+            if i > 0 {
+                result.push('\n');
+                if !same_group(&entries[i - 1].key, &entry.key) {
+                    result.push('\n');
+                }
+                result.push_str(&indent);
+            }
+            if entry.attrs.len() > 0 {
+                result.push_str(&entry.attrs);
+                result.push('\n');
+                result.push_str(&indent);
+            }
+            result.push_str(&entry.text);
+        }
+
+        let last = items.last().unwrap();
+        self.format_missing_with_indent(item_lo(&items[0]));
+        self.changes.push_str_span(last.span, &result);
+        self.last_pos = last.span.hi + BytePos(self.trailing_comment_len(last.span.hi) as u32);
+    }
+
+    // Which group an import belongs in, judged by the first segment of its
+    // path. Everything is in one group unless group_imports is set.
+    fn import_group(&self, path: &[String]) -> ImportGroup {
+        let first = match path.first() {
+            Some(first) if config!(group_imports) => first,
+            _ => return ImportGroup::Local,
+        };
+
+        if *first == "std" || *first == "core" || *first == "alloc" {
+            ImportGroup::Std
+        } else if self.extern_crates.iter().any(|name| name == first) {
+            ImportGroup::External
+        } else {
+            ImportGroup::Local
+        }
+    }
+
+    // The length of the text between pos and the end of its line, if that
+    // text is a comment (and 0 otherwise).
+    fn trailing_comment_len(&self, pos: BytePos) -> usize {
+        let loc = self.codemap.lookup_char_pos(pos);
+        let line = match loc.file.get_line(loc.line - 1) {
+            Some(line) => line,
+            None => return 0,
+        };
+        let start = line.char_indices().nth(loc.col.to_usize()).map_or(line.len(), |(i, _)| i);
+        let rest = line[start..].trim_right();
+        let comment = rest.trim_left();
+        if comment.len() > 0 && comment_len(comment) == Some(comment.len()) {
+            rest.len()
+        } else {
+            0
+        }
+    }
+
     // Basically just pretty prints a multi-item import.
     pub fn rewrite_use_list(&mut self,
                             block_indent: usize,
@@ -46,74 +522,658 @@ impl<'a> FmtVisitor<'a> {
                             multi_line_budget: usize,
                             path: &ast::Path,
                             path_list: &[ast::PathListItem],
-                            visibility: ast::Visibility) -> String {
-        let path_str = pprust::path_to_string(path);
+                            visibility: ast::Visibility,
+                            span: Span) -> String {
+        let vis = vis_str(visibility);
 
-        let vis = match visibility {
-            ast::Public => "pub ",
-            _ => ""
-        };
+        // 4 = `use `
+        let path_offset = block_indent + vis.len() + 4;
+        let path_str = path.rewrite(&self.get_context(), one_line_budget, path_offset)
+                           .unwrap_or_else(|| self.snippet(path.span));
 
-        if path_list.len() == 1 {
-            return rewrite_single_use_list(path_str, path_list[0], vis);
+        let used: Vec<_> = path_list.iter()
+                                    .filter(|vpi| !self.is_unused_import(vpi.span))
+                                    .collect();
+        match used.len() {
+            0 => return String::new(),
+            1 => return rewrite_single_use_list(path_str, *used[0], vis),
+            _ => {}
         }
 
-        // 2 = ::
-        let path_separation_w = if path_str.len() > 0 { 2 } else { 0 };
-        // 5 = "use " + {
-        let indent = path_str.len() + 5 + path_separation_w + vis.len();
+        let items = itemize_list(self.codemap,
+                                     Vec::new(),
+                                     path_list.iter(),
+                                     ",",
+                                     "}",
+                                     |vpi| vpi.span.lo,
+                                     |vpi| vpi.span.hi,
+                                     |vpi| match vpi.node {
+                                         ast::PathListItem_::PathListIdent{ name, .. } => {
+                                             token::get_ident(name).to_string()
+                                         }
+                                         ast::PathListItem_::PathListMod{ .. } => {
+                                             "self".to_owned()
+                                         }
+                                     },
+                                     span_after(span, "{", self.codemap),
+                                     span.hi);
 
-        // 2 = } + ;
-        let used_width = indent + 2;
+        // Drop unused imports, along with their comments.
+        let mut items: Vec<_> = items.into_iter()
+                                     .zip(path_list.iter())
+                                     .filter(|&(_, vpi)| !self.is_unused_import(vpi.span))
+                                     .map(|(item, _)| item)
+                                     .collect();
 
-        // Break as early as possible when we've blown our budget.
-        let remaining_line_budget = if used_width > one_line_budget {
-            0
-        } else {
-            one_line_budget - used_width
-        };
-        let remaining_multi_budget = if used_width > multi_line_budget {
-            0
-        } else {
-            multi_line_budget - used_width
+        // If `self` is in the list, put it first.
+        let has_self = match used.iter().position(|vpi| is_mod_item(vpi)) {
+            Some(i) => {
+                let self_item = items.remove(i);
+                items.insert(0, self_item);
+                true
+            }
+            None => false,
         };
 
-        let fmt = ListFormatting {
-            tactic: ListTactic::Mixed,
-            separator: ",",
-            trailing_separator: SeparatorTactic::Never,
-            indent: block_indent + indent,
-            h_width: remaining_line_budget,
-            v_width: remaining_multi_budget,
-        };
+        if config!(reorder_imports) {
+            let start = if has_self { 1 } else { 0 };
+            items[start..].sort_by(|a, b| {
+                compare_use_list_items(&a.item, &b.item, &self.module_names)
+            });
+        }
+        format_use_list(vis,
+                        &path_str,
+                        &items,
+                        block_indent,
+                        one_line_budget,
+                        multi_line_budget)
+    }
 
-        // TODO handle any comments inbetween items.
-        // If `self` is in the list, put it first.
-        let head = if path_list.iter().any(|vpi|
-            if let ast::PathListItem_::PathListMod{ .. } = vpi.node {
-                true
+    // Merge or split imports according to granularity. Returns the index of
+    // the first item contributing to each use, one of the use's imports (for
+    // its visibility, attributes and path) and the use itself.
+    fn combine_imports<'b>(&self,
+                           imports: &'b [(usize, FlatImport)],
+                           granularity: ImportsGranularity)
+                           -> Vec<(usize, &'b FlatImport, String)> {
+        // Imports which share visibility, attributes and module, with the last
+        // segment of each path.
+        let mut lists: Vec<(usize, &FlatImport, &[String], Vec<String>)> = Vec::new();
+        for &(index, ref import) in imports {
+            let prefix = &import.path[..import.path.len() - 1];
+            let name = import.path.last().unwrap().clone();
+
+            let shared = granularity != ImportsGranularity::Item && prefix.len() > 0;
+            let position = if shared {
+                lists.iter().position(|&(_, other, other_prefix, _)| {
+                    other.vis == import.vis && other.attrs == import.attrs &&
+                    other_prefix == prefix
+                })
             } else {
-                false
+                None
+            };
+            match position {
+                Some(i) => lists[i].3.push(name),
+                None => lists.push((index, import, prefix, vec![name])),
             }
-        ) {
-            Some(("self".to_owned(), String::new()))
-        } else {
-            None
-        };
+        }
+
+        lists.into_iter()
+             .map(|(index, import, prefix, names)| {
+                 let names = order_use_list(names, &self.module_names);
+                 (index, import, self.rewrite_import_list(import.vis, prefix, names))
+             })
+             .collect()
+    }
+
+    // Write `use prefix::{items};`, or `use prefix::item;` for a single item.
+    fn rewrite_import_list(&self, vis: &str, prefix: &[String], items: Vec<String>) -> String {
+        let path_str = prefix.connect("::");
+        if items.len() == 1 {
+            return if path_str.len() == 0 {
+                format!("{}use {};", vis, items[0])
+            } else if items[0] == "self" {
+                format!("{}use {};", vis, path_str)
+            } else {
+                format!("{}use {}::{};", vis, path_str, items[0])
+            };
+        }
+
+        let items: Vec<_> = items.into_iter().map(ListItem::from_str).collect();
+        let block_indent = self.block_indent;
+        format_use_list(vis,
+                        &path_str,
+                        &items,
+                        block_indent,
+                        config!(max_width) - block_indent,
+                        config!(ideal_width) - block_indent)
+    }
+}
+
+// Write `use path_str::{items};`, breaking the list over several lines if it
+// doesn't fit in one_line_budget.
+fn format_use_list(vis: &str,
+                   path_str: &str,
+                   items: &[ListItem],
+                   block_indent: usize,
+                   one_line_budget: usize, // excluding indentation
+                   multi_line_budget: usize)
+                   -> String {
+    // 2 = ::
+    let path_separation_w = if path_str.len() > 0 { 2 } else { 0 };
+    // 5 = "use " + {
+    let indent = path_str.len() + 5 + path_separation_w + vis.len();
+
+    // 2 = } + ;
+    let used_width = indent + 2;
+
+    // Break as early as possible when we've blown our budget.
+    let remaining_line_budget = if used_width > one_line_budget {
+        0
+    } else {
+        one_line_budget - used_width
+    };
+    let remaining_multi_budget = if used_width > multi_line_budget {
+        0
+    } else {
+        multi_line_budget - used_width
+    };
+
+    let fmt = ListFormatting {
+        tactic: ListTactic::Mixed,
+        separator: ",",
+        trailing_separator: SeparatorTactic::Never,
+        indent: block_indent + indent,
+        h_width: remaining_line_budget,
+        v_width: remaining_multi_budget,
+        align_after: None,
+        align_comments: config!(trailing_comment_align),
+    };
+
+    if path_str.len() == 0 {
+        format!("{}use {{{}}};", vis, write_list(items, &fmt))
+    } else {
+        format!("{}use {}::{{{}}};", vis, path_str, write_list(items, &fmt))
+    }
+}
+
+// A single import, e.g., `a::b::c` from `use a::b::{c, d};`. An import of the
+// module itself (`use a::b::{self};`) has `self` as its last segment.
+struct FlatImport {
+    vis: &'static str,
+    attrs: String,
+    path: Vec<String>,
+}
+
+// The imports of a use item, or None if the item can't be merged with others
+// (globs, renames and global paths).
+fn flatten_use(vp: &ast::ViewPath,
+               vis: &'static str,
+               attrs: &str,
+               unused_imports: &[Span])
+               -> Option<Vec<FlatImport>> {
+    let make_import = |path: Vec<String>| {
+        FlatImport {
+            vis: vis,
+            attrs: attrs.to_owned(),
+            path: path,
+        }
+    };
+
+    match vp.node {
+        ast::ViewPath_::ViewPathSimple(ident, ref path) => {
+            if path.global {
+                return None;
+            }
+            let segments = view_path_segments(vp);
+            if segments.last().map_or(true, |last| *last != token::get_ident(ident).to_string()) {
+                return None;
+            }
+            Some(vec![make_import(segments)])
+        }
+        ast::ViewPath_::ViewPathList(ref path, ref path_list) => {
+            let segments = view_path_segments(vp);
+            // `use {self};` is an error, which we leave alone.
+            if path.global || (segments.len() == 0 && path_list.iter().any(is_mod_item)) {
+                return None;
+            }
+            Some(path_list.iter()
+                          .filter(|vpi| !unused_imports.contains(&vpi.span))
+                          .map(|vpi| {
+                              let mut import_path = segments.clone();
+                              import_path.push(match vpi.node {
+                                  ast::PathListItem_::PathListIdent{ name, .. } => {
+                                      token::get_ident(name).to_string()
+                                  }
+                                  ast::PathListItem_::PathListMod{ .. } => "self".to_owned(),
+                              });
+                              make_import(import_path)
+                          })
+                          .collect())
+        }
+        ast::ViewPath_::ViewPathGlob(_) => None,
+    }
+}
+
+// Order the names in a use list, dropping duplicates.
+fn order_use_list(names: Vec<String>, module_names: &HashSet<String>) -> Vec<String> {
+    let mut result: Vec<String> = Vec::with_capacity(names.len());
+    for name in names {
+        if !result.contains(&name) {
+            result.push(name);
+        }
+    }
+
+    // As in rewrite_use_list, `self` goes first.
+    if let Some(i) = result.iter().position(|item| *item == "self") {
+        let self_item = result.remove(i);
+        result.insert(0, self_item);
+    }
+    if config!(reorder_imports) {
+        let start = if result.first().map_or(false, |item| *item == "self") { 1 } else { 0 };
+        result[start..].sort_by(|a, b| compare_use_list_items(a, b, module_names));
+    }
+
+    result
+}
+
+// Nested lists can't be parsed by this compiler (see ImportsGranularity), so
+// Nested is treated as Preserve.
+fn imports_granularity() -> ImportsGranularity {
+    match config!(imports_granularity) {
+        ImportsGranularity::Nested => ImportsGranularity::Preserve,
+        granularity => granularity,
+    }
+}
+
+fn vis_str(vis: ast::Visibility) -> &'static str {
+    match vis {
+        ast::Public => "pub ",
+        _ => "",
+    }
+}
+
+// The segments of the path of a use item, not including any list or glob.
+fn view_path_segments(vp: &ast::ViewPath) -> Vec<String> {
+    let path = match vp.node {
+        ast::ViewPath_::ViewPathSimple(_, ref path) |
+        ast::ViewPath_::ViewPathGlob(ref path) |
+        ast::ViewPath_::ViewPathList(ref path, _) => path,
+    };
+    path.segments.iter().map(|s| token::get_ident(s.identifier).to_string()).collect()
+}
+
+fn is_mod_item(vpi: &ast::PathListItem) -> bool {
+    match vpi.node {
+        ast::PathListItem_::PathListMod{ .. } => true,
+        _ => false,
+    }
+}
+
+// An item, or merged import, in a reordered run: its sort key, rewritten
+// attributes, and text including any trailing comment.
+struct RunEntry<K> {
+    key: K,
+    attrs: String,
+    text: String,
+}
+
+// Imports are grouped in this order.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+enum ImportGroup {
+    Std,
+    External,
+    Local,
+}
+
+// The start of an item including its attributes.
+fn item_lo(item: &ast::Item) -> BytePos {
+    outer_attrs(item).first().map_or(item.span.lo, |attr| attr.span.lo)
+}
+
+// The attributes before an item. An out-of-line mod also has the inner
+// attributes from its own file.
+fn outer_attrs(item: &ast::Item) -> Vec<ast::Attribute> {
+    item.attrs
+        .iter()
+        .filter(|attr| attr.node.style == ast::AttrStyle::AttrOuter)
+        .cloned()
+        .collect()
+}
+
+pub fn rewrite_mod_decl(item: &ast::Item) -> String {
+    format!("{}mod {};", vis_str(item.vis), token::get_ident(item.ident))
+}
+
+fn is_static_item(item: &ast::Item) -> bool {
+    match item.node {
+        ast::Item_::ItemConst(..) | ast::Item_::ItemStatic(..) => true,
+        _ => false,
+    }
+}
+
+// The kinds of item which are reordered in runs.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum RunKind {
+    Use,
+    ExternCrate,
+    ModDecl,
+}
+
+// Sort uses by their path, ignoring visibility.
+fn use_sort_key(use_str: &str) -> String {
+    let use_str = if use_str.starts_with("pub ") {
+        &use_str[4..]
+    } else {
+        use_str
+    };
+    use_str.trim_left_matches("use ").to_owned()
+}
+
+// Within a list, modules go before types, which go before functions (`self`
+// is put first by the callers). See use_list_kind.
+fn compare_use_list_items(a: &str, b: &str, module_names: &HashSet<String>) -> Ordering {
+    (use_list_kind(a, module_names), a).cmp(&(use_list_kind(b, module_names), b))
+}
+
+// The kinds of name in a use list, in the order they are sorted in.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+enum UseListKind {
+    Module,
+    Type,
+    Function,
+}
+
+// Names starting with an upper case letter are types (or traits or
+// constants). We can't tell a module from a function by its name, so we take
+// a name to be a module if it is used as a module elsewhere in the current
+// module (see module_names), and a function otherwise.
+fn use_list_kind(name: &str, module_names: &HashSet<String>) -> UseListKind {
+    if name.chars().next().map_or(false, |c| c.is_uppercase()) {
+        UseListKind::Type
+    } else if module_names.contains(name) {
+        UseListKind::Module
+    } else {
+        UseListKind::Function
+    }
+}
+
+// The names which are used as modules in the paths of items, i.e., which are
+// followed by `::`, not including nested modules. Only needed if
+// reorder_imports is set.
+fn module_names(items: &[P<ast::Item>]) -> HashSet<String> {
+    let mut collector = ModuleNames { names: HashSet::new() };
+    if config!(reorder_imports) {
+        for item in items {
+            collector.visit_item(item);
+        }
+    }
+    collector.names
+}
 
-        let items: Vec<_> = head.into_iter().chain(path_list.iter().filter_map(|vpi| {
-            match vpi.node {
-                ast::PathListItem_::PathListIdent{ name, .. } => {
-                    Some((token::get_ident(name).to_string(), String::new()))
+struct ModuleNames {
+    names: HashSet<String>,
+}
+
+impl<'v> Visitor<'v> for ModuleNames {
+    fn visit_item(&mut self, item: &'v ast::Item) {
+        match item.node {
+            // Nested modules have their own imports.
+            ast::Item_::ItemMod(_) => {}
+            ast::Item_::ItemUse(ref vp) => {
+                let segments = view_path_segments(vp);
+                // The path of a list or glob import is all modules.
+                let module_len = match vp.node {
+                    ast::ViewPath_::ViewPathSimple(..) => segments.len().saturating_sub(1),
+                    _ => segments.len(),
+                };
+                self.names.extend(segments.into_iter().take(module_len));
+            }
+            _ => visit::walk_item(self, item),
+        }
+    }
+
+    fn visit_path(&mut self, path: &'v ast::Path, _: ast::NodeId) {
+        let module_len = path.segments.len().saturating_sub(1);
+        for segment in &path.segments[..module_len] {
+            self.names.insert(token::get_ident(segment.identifier).to_string());
+        }
+        visit::walk_path(self, path);
+    }
+
+    // Macros are not expanded, so there is nothing to see.
+    fn visit_mac(&mut self, _: &'v ast::Mac) {}
+}
+
+// The spans of the imports in krate which may be removed if name resolution
+// finds they are unused. Lint levels are not applied to the lints which
+// resolution reports, so we skip any import where the unused_imports lint is
+// allowed. We also skip imports which code under a cfg or cfg_attr attribute in
+// the same module may use, since that code might be configured out.
+pub fn removable_imports(krate: &ast::Crate) -> Vec<Span> {
+    let mut collector = RemovableImports {
+        allowed: unused_imports_allowed(&krate.attrs, false),
+        cfg_uses: cfg_uses(&krate.module.items),
+        spans: Vec::new(),
+    };
+    for item in &krate.module.items {
+        collector.visit_item(item);
+    }
+    collector.spans
+}
+
+struct RemovableImports {
+    // Whether the unused_imports lint is allowed for the current item.
+    allowed: bool,
+    // What configured code in the current module may use.
+    cfg_uses: CfgUses,
+    spans: Vec<Span>,
+}
+
+impl<'v> Visitor<'v> for RemovableImports {
+    fn visit_item(&mut self, item: &'v ast::Item) {
+        let outer_allowed = self.allowed;
+        self.allowed = unused_imports_allowed(&item.attrs, outer_allowed);
+
+        match item.node {
+            ast::Item_::ItemUse(ref vp) => {
+                if !self.allowed {
+                    // Resolution reports each item of a list separately.
+                    match vp.node {
+                        ast::ViewPath_::ViewPathSimple(ident, _) => {
+                            if !self.cfg_uses.may_use(Some(&*token::get_ident(ident))) {
+                                self.spans.push(vp.span);
+                            }
+                        }
+                        ast::ViewPath_::ViewPathList(ref path, ref path_list) => {
+                            for vpi in path_list {
+                                let name = match vpi.node {
+                                    ast::PathListItem_::PathListIdent{ name, .. } => {
+                                        token::get_ident(name).to_string()
+                                    }
+                                    ast::PathListItem_::PathListMod{ .. } => {
+                                        path.segments.last().map_or(String::new(), |segment| {
+                                            token::get_ident(segment.identifier).to_string()
+                                        })
+                                    }
+                                };
+                                if !self.cfg_uses.may_use(Some(&name[..])) {
+                                    self.spans.push(vpi.span);
+                                }
+                            }
+                        }
+                        ast::ViewPath_::ViewPathGlob(_) => {
+                            if !self.cfg_uses.may_use(None) {
+                                self.spans.push(vp.span);
+                            }
+                        }
+                    }
                 }
-                // Skip `self`, because we added it above.
-                ast::PathListItem_::PathListMod{ .. } => None,
             }
-        })).collect();
-        if path_str.len() == 0 {
-            format!("{}use {{{}}};", vis, write_list(&items, &fmt))
-        } else {
-            format!("{}use {}::{{{}}};", vis, path_str, write_list(&items, &fmt))
+            ast::Item_::ItemMod(ref module) => {
+                let outer_cfg_uses = mem::replace(&mut self.cfg_uses, cfg_uses(&module.items));
+                visit::walk_item(self, item);
+                self.cfg_uses = outer_cfg_uses;
+            }
+            _ => visit::walk_item(self, item),
+        }
+
+        self.allowed = outer_allowed;
+    }
+
+    // Macros are not expanded, so there is nothing to see.
+    fn visit_mac(&mut self, _: &'v ast::Mac) {}
+}
+
+// Whether the unused_imports lint is allowed for an item with attrs, given
+// whether it is allowed for the enclosing item. The `unused` lint group
+// includes unused_imports.
+fn unused_imports_allowed(attrs: &[ast::Attribute], outer_allowed: bool) -> bool {
+    let mut allowed = outer_allowed;
+    for attr in attrs {
+        let level_allows = match &*attr.name() {
+            "allow" => true,
+            "warn" | "deny" | "forbid" => false,
+            _ => continue,
+        };
+        let names_lint = attr.meta_item_list().map_or(false, |lints| {
+            lints.iter().any(|lint| {
+                let name = lint.name();
+                &*name == "unused_imports" || &*name == "unused"
+            })
+        });
+        if names_lint {
+            allowed = level_allows;
+        }
+    }
+    allowed
+}
+
+// What code under a cfg or cfg_attr attribute in a module may use.
+struct CfgUses {
+    // Whether there is any such code.
+    found: bool,
+    // Whether it may use any name, because it contains a macro or a method call
+    // (which may need a trait import), which we can't see into.
+    any: bool,
+    // The names in its paths and patterns.
+    names: HashSet<String>,
+}
+
+impl CfgUses {
+    // Whether the code may use an import of name, or a glob import if name is
+    // None.
+    fn may_use(&self, name: Option<&str>) -> bool {
+        match name {
+            Some(name) => self.any || self.names.contains(name),
+            None => self.found,
+        }
+    }
+}
+
+// What code under a cfg or cfg_attr attribute in items, or anything inside
+// them, may use. Nested modules are not counted, nor are attributes on the
+// modules themselves, since a module has its own imports.
+fn cfg_uses(items: &[P<ast::Item>]) -> CfgUses {
+    let mut finder = CfgUseFinder {
+        in_cfg: false,
+        uses: CfgUses {
+            found: false,
+            any: false,
+            names: HashSet::new(),
+        },
+    };
+    for item in items {
+        finder.visit_item(item);
+    }
+    finder.uses
+}
+
+struct CfgUseFinder {
+    // Whether the current node is under a cfg or cfg_attr attribute.
+    in_cfg: bool,
+    uses: CfgUses,
+}
+
+impl CfgUseFinder {
+    // Walks a node with attrs, noting whether it is under a cfg attribute.
+    fn walk_with_attrs<F>(&mut self, attrs: &[ast::Attribute], walk: F)
+        where F: FnOnce(&mut CfgUseFinder)
+    {
+        let outer_in_cfg = self.in_cfg;
+        if attrs.iter().any(|a| a.check_name("cfg") || a.check_name("cfg_attr")) {
+            self.in_cfg = true;
+            self.uses.found = true;
+        }
+        walk(self);
+        self.in_cfg = outer_in_cfg;
+    }
+}
+
+impl<'v> Visitor<'v> for CfgUseFinder {
+    fn visit_item(&mut self, item: &'v ast::Item) {
+        match item.node {
+            ast::Item_::ItemMod(_) => {}
+            _ => self.walk_with_attrs(&item.attrs, |finder| visit::walk_item(finder, item)),
+        }
+    }
+
+    fn visit_trait_item(&mut self, trait_item: &'v ast::TraitItem) {
+        self.walk_with_attrs(&trait_item.attrs,
+                             |finder| visit::walk_trait_item(finder, trait_item));
+    }
+
+    fn visit_impl_item(&mut self, impl_item: &'v ast::ImplItem) {
+        self.walk_with_attrs(&impl_item.attrs,
+                             |finder| visit::walk_impl_item(finder, impl_item));
+    }
+
+    fn visit_foreign_item(&mut self, foreign_item: &'v ast::ForeignItem) {
+        self.walk_with_attrs(&foreign_item.attrs,
+                             |finder| visit::walk_foreign_item(finder, foreign_item));
+    }
+
+    fn visit_struct_field(&mut self, field: &'v ast::StructField) {
+        self.walk_with_attrs(&field.node.attrs,
+                             |finder| visit::walk_struct_field(finder, field));
+    }
+
+    fn visit_variant(&mut self, variant: &'v ast::Variant, generics: &'v ast::Generics) {
+        self.walk_with_attrs(&variant.node.attrs,
+                             |finder| visit::walk_variant(finder, variant, generics));
+    }
+
+    fn visit_path(&mut self, path: &'v ast::Path, _: ast::NodeId) {
+        if self.in_cfg {
+            for segment in &path.segments {
+                self.uses.names.insert(token::get_ident(segment.identifier).to_string());
+            }
+        }
+        visit::walk_path(self, path);
+    }
+
+    fn visit_pat(&mut self, pat: &'v ast::Pat) {
+        // A single identifier pattern may name an imported constant.
+        if let ast::Pat_::PatIdent(_, ref ident, _) = pat.node {
+            if self.in_cfg {
+                self.uses.names.insert(token::get_ident(ident.node).to_string());
+            }
+        }
+        visit::walk_pat(self, pat);
+    }
+
+    fn visit_expr(&mut self, ex: &'v ast::Expr) {
+        if let ast::Expr_::ExprMethodCall(..) = ex.node {
+            if self.in_cfg {
+                self.uses.any = true;
+            }
+        }
+        visit::walk_expr(self, ex);
+    }
+
+    fn visit_mac(&mut self, _: &'v ast::Mac) {
+        if self.in_cfg {
+            self.uses.any = true;
         }
     }
 }
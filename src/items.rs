@@ -10,14 +10,19 @@
 
 // Formatting top-level items - functions, structs, enums, traits, impls.
 
-use {ReturnIndent, BraceStyle};
-use utils::make_indent;
-use lists::{write_list, ListFormatting, SeparatorTactic, ListTactic};
+use {ReturnIndent, BraceStyle, IndentStyle, BlockIndentStyle};
+use utils::{make_indent, last_line_offset, first_line_width, span_after, fits_in};
+use lists::{write_list, itemize_list, visual_tactic, ListItem, ListFormatting, SeparatorTactic,
+            ListTactic, ListLayout};
 use visitor::FmtVisitor;
+use types::rewrite_bound_list;
+use expr::{block_context, wrap_list};
+use rewrite::{Rewrite, RewriteContext};
 use syntax::{ast, abi};
 use syntax::codemap::{self, Span, BytePos};
 use syntax::print::pprust;
 use syntax::parse::token;
+use syntax::ptr::P;
 
 impl<'a> FmtVisitor<'a> {
     pub fn rewrite_fn(&mut self,
@@ -31,21 +36,21 @@ impl<'a> FmtVisitor<'a> {
                       abi: &abi::Abi,
                       vis: ast::Visibility,
                       span_end: BytePos)
-        -> String
+        -> Option<String>
     {
-        let newline_brace = self.newline_for_brace(&generics.where_clause);
-
-        let mut result = self.rewrite_fn_base(indent,
-                                              ident,
-                                              fd,
-                                              explicit_self,
-                                              generics,
-                                              unsafety,
-                                              constness,
-                                              abi,
-                                              vis,
-                                              span_end,
-                                              newline_brace);
+        let newline_brace = self.newline_for_brace(config!(fn_brace_style), &generics.where_clause);
+
+        let mut result = try_opt!(self.rewrite_fn_base(indent,
+                                                       ident,
+                                                       fd,
+                                                       explicit_self,
+                                                       generics,
+                                                       unsafety,
+                                                       constness,
+                                                       abi,
+                                                       vis,
+                                                       span_end,
+                                                       newline_brace));
 
         // Prepare for the function body by possibly adding a newline and indent.
         // FIXME we'll miss anything between the end of the signature and the start
@@ -57,7 +62,7 @@ impl<'a> FmtVisitor<'a> {
             result.push(' ');
         }
 
-        result
+        Some(result)
     }
 
     pub fn rewrite_required_fn(&mut self,
@@ -65,27 +70,58 @@ impl<'a> FmtVisitor<'a> {
                                ident: ast::Ident,
                                sig: &ast::MethodSig,
                                span: Span)
-        -> String
+        -> Option<String>
     {
         // Drop semicolon or it will be interpreted as comment
         let span_end = span.hi - BytePos(1);
 
-        let mut result = self.rewrite_fn_base(indent,
-                                              ident,
-                                              &sig.decl,
-                                              Some(&sig.explicit_self),
-                                              &sig.generics,
-                                              &sig.unsafety,
-                                              &sig.constness,
-                                              &sig.abi,
-                                              ast::Visibility::Inherited,
-                                              span_end,
-                                              false);
+        let mut result = try_opt!(self.rewrite_fn_base(indent,
+                                                       ident,
+                                                       &sig.decl,
+                                                       Some(&sig.explicit_self),
+                                                       &sig.generics,
+                                                       &sig.unsafety,
+                                                       &sig.constness,
+                                                       &sig.abi,
+                                                       ast::Visibility::Inherited,
+                                                       span_end,
+                                                       false));
 
         // Re-attach semicolon
         result.push(';');
 
-        result
+        Some(result)
+    }
+
+    // A function declared in an extern block, e.g., `fn foo(x: c_int) -> c_int;`.
+    pub fn rewrite_foreign_fn(&mut self,
+                              indent: usize,
+                              ident: ast::Ident,
+                              fd: &ast::FnDecl,
+                              generics: &ast::Generics,
+                              vis: ast::Visibility,
+                              span: Span)
+        -> Option<String>
+    {
+        // Drop semicolon or it will be interpreted as comment
+        let span_end = span.hi - BytePos(1);
+
+        let mut result = try_opt!(self.rewrite_fn_base(indent,
+                                                       ident,
+                                                       fd,
+                                                       None,
+                                                       generics,
+                                                       &ast::Unsafety::Normal,
+                                                       &ast::Constness::NotConst,
+                                                       &abi::Rust,
+                                                       vis,
+                                                       span_end,
+                                                       false));
+
+        // Re-attach semicolon
+        result.push(';');
+
+        Some(result)
     }
 
     fn rewrite_fn_base(&mut self,
@@ -100,7 +136,7 @@ impl<'a> FmtVisitor<'a> {
                        vis: ast::Visibility,
                        span_end: BytePos,
                        newline_brace: bool)
-        -> String
+        -> Option<String>
     {
         // FIXME we'll lose any comments in between parts of the function decl, but anyone
         // who comments there probably deserves what they get.
@@ -134,46 +170,61 @@ impl<'a> FmtVisitor<'a> {
                                                generics_indent,
                                                span_for_return(&fd.output).lo));
 
-        let ret_str = self.rewrite_return(&fd.output);
-
-        // Args.
-        let (one_line_budget, multi_line_budget, mut arg_indent) =
-            self.compute_budgets_for_args(&result, indent, ret_str.len(), newline_brace);
-
-        debug!("rewrite_fn: one_line_budget: {}, multi_line_budget: {}, arg_indent: {}",
-               one_line_budget, multi_line_budget, arg_indent);
+        // We don't know yet where the return type will end up, so assume the
+        // worst case of it going on its own line.
+        let ret_indent = indent + config!(tab_spaces);
+        let ret_str = rewrite_return(&self.get_context(),
+                                     &fd.output,
+                                     config!(max_width).saturating_sub(ret_indent),
+                                     ret_indent).unwrap_or_else(|| {
+            format!("-> {}", self.snippet(span_for_return(&fd.output)))
+        });
 
-        // Check if vertical layout was forced by compute_budget_for_args.
-        if one_line_budget <= 0 {
-            if config!(fn_args_paren_newline) {
-                result.push('\n');
-                result.push_str(&make_indent(arg_indent));
-                arg_indent = arg_indent + 1; // extra space for `(`
-                result.push('(');
-            } else {
-                result.push_str("(\n");
-                result.push_str(&make_indent(arg_indent));
+        // Args. We use the first layout in which they fit, or give up.
+        let args_width = config!(max_width).saturating_sub(last_line_offset(&result, indent));
+        let block_style = config!(indent_style) == IndentStyle::Block;
+        let mut args = None;
+        for &layout in &[ListLayout::Visual, ListLayout::OnePerLine] {
+            let budgets = match self.compute_budgets_for_args(&result,
+                                                              indent,
+                                                              ret_str.len(),
+                                                              newline_brace,
+                                                              layout) {
+                Some(budgets) => budgets,
+                None => continue,
+            };
+            let (args_str, arg_indent) = self.rewrite_fn_args(fd, explicit_self, indent, budgets);
+            // With block indentation, visual layout is only for one line.
+            if block_style && layout == ListLayout::Visual && args_str.contains('\n') {
+                continue;
+            }
+            if fits_in(&args_str, args_width) {
+                args = Some((args_str, arg_indent));
+                break;
             }
-        } else {
-            result.push('(');
         }
-
-        result.push_str(&self.rewrite_args(&fd.inputs,
-                                           explicit_self,
-                                           one_line_budget,
-                                           multi_line_budget,
-                                           arg_indent,
-                                           span_for_return(&fd.output)));
-        result.push(')');
+        let (args_str, arg_indent) = try_opt!(args);
+        result.push_str(&args_str);
 
         // Return type.
         if ret_str.len() > 0 {
             // If we've already gone multi-line, or the return type would push
-            // over the max width, then put the return type on a new line.
-            if result.contains("\n") ||
-               result.len() + indent + ret_str.len() > config!(max_width) {
+            // over the max width, then put the return type on a new line. With
+            // block indentation, the closing paren starts the last line, so
+            // only its width matters.
+            let ret_on_new_line = match config!(indent_style) {
+                IndentStyle::Visual => {
+                    result.contains("\n") ||
+                    result.len() + indent + ret_str.len() > config!(max_width)
+                }
+                IndentStyle::Block => {
+                    // 1 = space before the return type
+                    last_line_offset(&result, indent) + ret_str.len() + 1 > config!(max_width)
+                }
+            };
+            if ret_on_new_line {
                 let indent = match config!(fn_return_indent) {
-                    ReturnIndent::WithWhereClause => indent + 4,
+                    ReturnIndent::WithWhereClause => where_indent(indent),
                     // TODO we might want to check that using the arg indent doesn't
                     // blow our budget, and if it does, then fallback to the where
                     // clause indent.
@@ -205,13 +256,71 @@ impl<'a> FmtVisitor<'a> {
         }
 
         // Where clause.
-        result.push_str(&self.rewrite_where_clause(where_clause, indent, span_end));
+        let where_offset = last_line_offset(&result, indent);
+        // 2 = ` {` or `;`
+        let where_budget = config!(max_width).saturating_sub(where_offset + 2);
+        result.push_str(&self.rewrite_where_clause(where_clause,
+                                                   indent,
+                                                   where_budget,
+                                                   where_offset,
+                                                   span_end));
+
+        Some(result)
+    }
 
-        result
+    // The parenthesised arguments of a function, laid out with the given
+    // budgets, and the indent of the arguments. indent is the indent of the
+    // function.
+    fn rewrite_fn_args(&self,
+                       fd: &ast::FnDecl,
+                       explicit_self: Option<&ast::ExplicitSelf>,
+                       indent: usize,
+                       budgets: (usize, usize, usize))
+        -> (String, usize)
+    {
+        let (one_line_budget, multi_line_budget, mut arg_indent) = budgets;
+        debug!("rewrite_fn: one_line_budget: {}, multi_line_budget: {}, arg_indent: {}",
+               one_line_budget, multi_line_budget, arg_indent);
+
+        let mut result = String::new();
+        let block_style = config!(indent_style) == IndentStyle::Block;
+        // Check if vertical layout was forced by compute_budget_for_args.
+        if one_line_budget <= 0 {
+            if block_style {
+                result.push_str("(\n");
+                result.push_str(&make_indent(arg_indent));
+            } else if config!(fn_args_paren_newline) {
+                result.push('\n');
+                result.push_str(&make_indent(arg_indent));
+                arg_indent = arg_indent + 1; // extra space for `(`
+                result.push('(');
+            } else {
+                result.push_str("(\n");
+                result.push_str(&make_indent(arg_indent));
+            }
+        } else {
+            result.push('(');
+        }
+
+        result.push_str(&self.rewrite_args(&fd.inputs,
+                                           fd.variadic,
+                                           explicit_self,
+                                           one_line_budget,
+                                           multi_line_budget,
+                                           arg_indent,
+                                           span_for_return(&fd.output)));
+        if one_line_budget <= 0 && block_style {
+            result.push('\n');
+            result.push_str(&make_indent(indent));
+        }
+        result.push(')');
+
+        (result, arg_indent)
     }
 
     fn rewrite_args(&self,
                     args: &[ast::Arg],
+                    variadic: bool,
                     explicit_self: Option<&ast::ExplicitSelf>,
                     one_line_budget: usize,
                     multi_line_budget: usize,
@@ -219,7 +328,11 @@ impl<'a> FmtVisitor<'a> {
                     ret_span: Span)
         -> String
     {
-        let mut arg_item_strs: Vec<_> = args.iter().map(|a| self.rewrite_fn_input(a)).collect();
+        let mut arg_item_strs: Vec<_> = args.iter()
+                                            .map(|a| self.rewrite_fn_input(a,
+                                                                           multi_line_budget,
+                                                                           arg_indent))
+                                            .collect();
         // Account for sugary self.
         let mut min_args = 1;
         if let Some(explicit_self) = explicit_self {
@@ -237,7 +350,12 @@ impl<'a> FmtVisitor<'a> {
                     min_args = 2;
                 }
                 ast::ExplicitSelf_::SelfExplicit(ref ty, _) => {
-                    arg_item_strs[0] = format!("self: {}", pprust::ty_to_string(ty));
+                    // 6 = `self: `
+                    let ty_str = ty.rewrite(&self.get_context(),
+                                            multi_line_budget.saturating_sub(6),
+                                            arg_indent + 6)
+                                   .unwrap_or_else(|| self.snippet(ty.span));
+                    arg_item_strs[0] = format!("self: {}", ty_str);
                 }
                 ast::ExplicitSelf_::SelfValue(_) => {
                     assert!(args.len() >= 1, "&[ast::Arg] shouldn't be empty.");
@@ -263,104 +381,77 @@ impl<'a> FmtVisitor<'a> {
         }
 
         // Comments between args
-        let mut arg_comments = Vec::new();
+        let mut arg_items = Vec::new();
         if min_args == 2 {
-            arg_comments.push("".to_owned());
+            arg_items.push(ListItem::from_str(""));
         }
         // TODO if there are no args, there might still be a comment, but without
         // spans for the comment or parens, there is no chance of getting it right.
         // You also don't get to put a comment on self, unless it is explicit.
         if args.len() >= min_args {
-            arg_comments = self.make_comments_for_list(arg_comments,
-                                                       args[min_args-1..].iter(),
-                                                       ",",
-                                                       ")",
-                                                       |arg| arg.pat.span.lo,
-                                                       |arg| arg.ty.span.hi,
-                                                       ret_span.lo);
+            let first_lo = args[min_args - 1].pat.span.lo;
+            arg_items = itemize_list(self.codemap,
+                                     arg_items,
+                                     args[min_args-1..].iter(),
+                                     ",",
+                                     ")",
+                                     |arg| arg.pat.span.lo,
+                                     |arg| arg.ty.span.hi,
+                                     |_| String::new(),
+                                     first_lo,
+                                     ret_span.lo);
         }
 
-        debug!("comments: {:?}", arg_comments);
-
-        // If there are // comments, keep them multi-line.
-        let mut list_tactic = ListTactic::HorizontalVertical;
-        if arg_comments.iter().any(|c| c.contains("//")) {
-            list_tactic = ListTactic::Vertical;
+        // C-variadic functions, e.g., `fn printf(format: *const c_char, ...)`.
+        if variadic {
+            // The comment after the last real argument will have picked up
+            // the `...`.
+            if let Some(item) = arg_items.last_mut() {
+                item.post_comment = item.post_comment
+                                        .as_ref()
+                                        .map(|c| c.trim_left_matches("...").trim().to_owned())
+                                        .and_then(|c| if c.len() > 0 { Some(c) } else { None });
+            }
+            arg_item_strs.push("...".to_owned());
+            arg_items.push(ListItem::from_str(""));
         }
 
-        assert_eq!(arg_item_strs.len(), arg_comments.len());
-        let arg_strs: Vec<_> = arg_item_strs.into_iter().zip(arg_comments.into_iter()).collect();
+        assert_eq!(arg_item_strs.len(), arg_items.len());
+        for (item, s) in arg_items.iter_mut().zip(arg_item_strs.into_iter()) {
+            item.item = s;
+        }
 
         let fmt = ListFormatting {
-            tactic: list_tactic,
+            tactic: ListTactic::HorizontalVertical,
             separator: ",",
             trailing_separator: SeparatorTactic::Never,
             indent: arg_indent,
             h_width: one_line_budget,
             v_width: multi_line_budget,
+            align_after: None,
+            align_comments: config!(trailing_comment_align),
         };
 
-        write_list(&arg_strs, &fmt)
-    }
-
-    // Gets comments in between items of a list.
-    fn make_comments_for_list<T, I, F1, F2>(&self,
-                                            prefix: Vec<String>,
-                                            mut it: I,
-                                            separator: &str,
-                                            terminator: &str,
-                                            get_lo: F1,
-                                            get_hi: F2,
-                                            next_span_start: BytePos)
-        -> Vec<String>
-        where I: Iterator<Item=T>,
-              F1: Fn(&T) -> BytePos,
-              F2: Fn(&T) -> BytePos
-    {
-        let mut result = prefix;
-
-        let mut prev_end = get_hi(&it.next().unwrap());
-        for item in it {
-            let cur_start = get_lo(&item);
-            let snippet = self.snippet(codemap::mk_sp(prev_end, cur_start));
-            let mut snippet = snippet.trim();
-            let white_space: &[_] = &[' ', '\t'];
-            if snippet.starts_with(separator) {
-                snippet = snippet[separator.len()..].trim_matches(white_space);
-            } else if snippet.ends_with(separator) {
-                snippet = snippet[..snippet.len()-separator.len()].trim_matches(white_space);
-            }
-            result.push(snippet.to_owned());
-            prev_end = get_hi(&item);
-        }
-        // Get the last commment.
-        // FIXME If you thought the crap with the commas was ugly, just wait.
-        // This is awful. We're going to look from the last item span to the
-        // start of the return type span, then we drop everything after the
-        // first closing paren. Obviously, this will break if there is a
-        // closing paren in the comment.
-        // The fix is comments in the AST or a span for the closing paren.
-        let snippet = self.snippet(codemap::mk_sp(prev_end, next_span_start));
-        let snippet = snippet.trim();
-        let snippet = &snippet[..snippet.find(terminator)
-                                    .unwrap_or(snippet.find(separator).unwrap_or(snippet.len()))];
-        let snippet = snippet.trim();
-        result.push(snippet.to_owned());
-
-        result
+        write_list(&arg_items, &fmt)
     }
 
+    // The one line budget, multi-line budget and indent of the arguments of
+    // a function in the given layout, or None if they can't be laid out that
+    // way. A one line budget of 0 forces the arguments onto their own lines.
     fn compute_budgets_for_args(&self,
                                 result: &String,
                                 indent: usize,
                                 ret_str_len: usize,
-                                newline_brace: bool)
-        -> (usize, usize, usize)
+                                newline_brace: bool,
+                                layout: ListLayout)
+        -> Option<(usize, usize, usize)>
     {
-        let mut budgets = None;
-
         // Try keeping everything on the same line
-        if !result.contains("\n") {
+        if layout == ListLayout::Visual {
+            if result.contains("\n") {
+                return None;
+            }
+
             // 3 = `() `, space is before ret_string
             let mut used_space = indent + result.len() + ret_str_len + 3;
             if !newline_brace {
@@ -378,32 +469,30 @@ impl<'a> FmtVisitor<'a> {
             debug!("compute_budgets_for_args: used_space: {}, max_space: {}",
                    used_space, max_space);
             if used_space < max_space {
-                budgets = Some((one_line_budget,
-                                max_space - used_space,
-                                indent + result.len() + 1));
+                return Some((one_line_budget,
+                             max_space - used_space,
+                             indent + result.len() + 1));
             }
+            return None;
         }
 
-        // Didn't work. we must force vertical layout and put args on a newline.
-        if let None = budgets {
-            let new_indent = indent + config!(tab_spaces);
-            let used_space = new_indent + 2; // account for `(` and `)`
-            let max_space = config!(ideal_width) + config!(leeway);
-            if used_space > max_space {
-                // Whoops! bankrupt.
-                // TODO take evasive action, perhaps kill the indent or something.
-            } else {
-                budgets = Some((0, max_space - used_space, new_indent));
-            }
+        // Force vertical layout and put args on a newline.
+        let new_indent = indent + config!(tab_spaces);
+        let used_space = new_indent + 2; // account for `(` and `)`
+        let max_space = config!(ideal_width) + config!(leeway);
+        if used_space > max_space {
+            // Bankrupt, the caller will leave the function as it is.
+            return None;
         }
-
-        budgets.unwrap()
+        Some((0, max_space - used_space, new_indent))
     }
 
-    fn newline_for_brace(&self, where_clause: &ast::WhereClause) -> bool {
-        match config!(fn_brace_style) {
+    fn newline_for_brace(&self, brace_style: BraceStyle, where_clause: &ast::WhereClause) -> bool {
+        match brace_style {
             BraceStyle::AlwaysNextLine => true,
-            BraceStyle::SameLineWhere if where_clause.predicates.len() > 0 => true,
+            BraceStyle::SameLineWhere if where_clause.predicates.len() > 0 => {
+                config!(where_brace_newline)
+            }
             _ => false,
         }
     }
@@ -415,7 +504,7 @@ impl<'a> FmtVisitor<'a> {
                         generics: &ast::Generics,
                         span: Span)
     {
-        let header_str = self.struct_header(ident, vis);
+        let header_str = self.format_header("struct ", ident, vis);
         self.changes.push_str_span(span, &header_str);
 
         if struct_def.fields.len() == 0 {
@@ -428,37 +517,271 @@ impl<'a> FmtVisitor<'a> {
             return;
         }
 
-        let mut generics_buf = String::new();
-        let generics_str = self.rewrite_generics(generics, self.block_indent, struct_def.fields[0].span.lo);
-        generics_buf.push_str(&generics_str);
-
-        if generics.where_clause.predicates.len() > 0 {
-            generics_buf.push_str(&self.rewrite_where_clause(&generics.where_clause,
-                                                             self.block_indent,
-                                                             struct_def.fields[0].span.lo));
-            generics_buf.push_str(&make_indent(self.block_indent));
-            generics_buf.push_str("\n{");
-
-        } else {
-            generics_buf.push_str(" {");
-        }
-        self.changes.push_str_span(span, &generics_buf);
+        let generics_str = self.format_generics(generics,
+                                                self.block_indent + header_str.len(),
+                                                struct_def.fields[0].span.lo);
+        self.changes.push_str_span(span, &generics_str);
 
         let struct_snippet = self.snippet(span);
         // FIXME this will give incorrect results if there is a { in a commet.
         self.last_pos = span.lo + BytePos(struct_snippet.find('{').unwrap() as u32 + 1);
 
         self.block_indent += config!(tab_spaces);
+        let name_width = if config!(struct_field_align) {
+            self.field_name_width(&struct_def.fields)
+        } else {
+            0
+        };
         for (i, f) in struct_def.fields.iter().enumerate() {
-            self.visit_field(f, i == struct_def.fields.len() - 1, span.lo, &struct_snippet);
+            self.visit_field(f,
+                             i == struct_def.fields.len() - 1,
+                             name_width,
+                             span.lo,
+                             &struct_snippet);
+        }
+        self.block_indent -= config!(tab_spaces);
+
+        self.format_missing_block_end(span.lo + BytePos(struct_snippet.rfind('}').unwrap() as u32));
+        self.changes.push_str_span(span, "}");
+    }
+
+    pub fn visit_enum(&mut self,
+                      ident: ast::Ident,
+                      vis: ast::Visibility,
+                      enum_def: &ast::EnumDef,
+                      generics: &ast::Generics,
+                      span: Span)
+    {
+        let header_str = self.format_header("enum ", ident, vis);
+        self.changes.push_str_span(span, &header_str);
+
+        let enum_snippet = self.snippet(span);
+        // FIXME this will give incorrect results if there is a { in a comment.
+        let body_start = span.lo + BytePos(enum_snippet.find('{').unwrap() as u32 + 1);
+        let generics_str = self.format_generics(generics,
+                                                self.block_indent + header_str.len(),
+                                                body_start);
+        self.changes.push_str_span(span, &generics_str);
+
+        self.last_pos = body_start;
+        self.block_indent += config!(tab_spaces);
+        for (i, v) in enum_def.variants.iter().enumerate() {
+            let last_variant = i == enum_def.variants.len() - 1;
+            let next_span_start = if last_variant {
+                span.hi
+            } else {
+                enum_def.variants[i + 1].span.lo
+            };
+
+            self.visit_variant(v, last_variant, next_span_start);
         }
         self.block_indent -= config!(tab_spaces);
 
-        self.format_missing_with_indent(span.lo + BytePos(struct_snippet.rfind('}').unwrap() as u32));
+        self.format_missing_block_end(span.lo + BytePos(enum_snippet.rfind('}').unwrap() as u32));
         self.changes.push_str_span(span, "}");
     }
 
-    fn struct_header(&self,
+    // Variant of an enum
+    fn visit_variant(&mut self,
+                     variant: &ast::Variant,
+                     last_variant: bool,
+                     // Start of the next variant or the end of the enum.
+                     next_span_start: BytePos)
+    {
+        if self.visit_attrs(&variant.node.attrs) {
+            return;
+        }
+        self.format_missing_with_indent(variant.span.lo);
+
+        let vis = if variant.node.vis == ast::Visibility::Public {
+            "pub "
+        } else {
+            ""
+        };
+        let mut result = format!("{}{}", vis, &token::get_ident(variant.node.name));
+
+        match variant.node.kind {
+            ast::VariantKind::TupleVariantKind(ref args) => {
+                if args.len() > 0 {
+                    result.push('(');
+                    let indent = self.block_indent + result.len();
+                    // 2 = `)` and the trailing comma
+                    let budget = config!(max_width).saturating_sub(indent + 2);
+                    let items = itemize_list(self.codemap,
+                                             Vec::new(),
+                                             args.iter(),
+                                             ",",
+                                             ")",
+                                             |arg| arg.ty.span.lo,
+                                             |arg| arg.ty.span.hi,
+                                             |arg| {
+                                                 arg.ty
+                                                    .rewrite(&self.get_context(), budget, indent)
+                                                    .unwrap_or_else(|| self.snippet(arg.ty.span))
+                                             },
+                                             span_after(variant.span, "(", self.codemap),
+                                             next_span_start);
+
+                    result.push_str(&self.write_variant_list(items, budget, indent));
+                    result.push(')');
+                }
+            }
+            ast::VariantKind::StructVariantKind(ref struct_def) => {
+                let fields_str = self.rewrite_struct_variant_fields(struct_def,
+                                                                    variant.span,
+                                                                    result.len(),
+                                                                    next_span_start);
+                result.push_str(&fields_str);
+            }
+        }
+
+        if let Some(ref expr) = variant.node.disr_expr {
+            result.push_str(" = ");
+            let offset = self.block_indent + result.len();
+            // 1 = trailing comma
+            let budget = config!(max_width).saturating_sub(offset + 1);
+            let expr_str = expr.rewrite(&self.get_context(), budget, offset)
+                               .unwrap_or_else(|| self.snippet(expr.span));
+            result.push_str(&expr_str);
+        }
+
+        self.changes.push_str_span(variant.span, &result);
+        self.finish_variant(variant.span, last_variant, next_span_start);
+    }
+
+    // Write the separating comma (if any) after a variant and skip over the
+    // original one.
+    fn finish_variant(&mut self, span: Span, last_variant: bool, next_span_start: BytePos) {
+        if !last_variant || config!(enum_trailing_comma) {
+            self.changes.push_str_span(span, ",");
+        }
+
+        let snippet = self.snippet(codemap::mk_sp(span.hi, next_span_start));
+        // FIXME a comma in a comment will break this hack.
+        let comma_pos = match snippet.find(',') {
+            Some(i) => i + 1,
+            None => 0,
+        };
+        self.last_pos = span.hi + BytePos(comma_pos as u32);
+    }
+
+    // The `{ a: A, b: B }` of a struct-like variant. Short field lists go on
+    // one line, otherwise each field gets its own line.
+    fn rewrite_struct_variant_fields(&self,
+                                     struct_def: &ast::StructDef,
+                                     span: Span,
+                                     used_width: usize,
+                                     next_span_start: BytePos)
+        -> String
+    {
+        if struct_def.fields.len() == 0 {
+            return " {}".to_owned();
+        }
+
+        let inner_indent = self.block_indent + config!(tab_spaces);
+        let field_str = |field: &ast::StructField| {
+            let vis = match field.node.kind {
+                ast::StructFieldKind::NamedField(_, ast::Visibility::Public) |
+                ast::StructFieldKind::UnnamedField(ast::Visibility::Public) => "pub ",
+                _ => "",
+            };
+            let name = match field.node.kind {
+                ast::StructFieldKind::NamedField(ident, _) => {
+                    format!("{}: ", &token::get_ident(ident))
+                }
+                ast::StructFieldKind::UnnamedField(_) => String::new(),
+            };
+            let offset = inner_indent + vis.len() + name.len();
+            // 1 = trailing comma
+            let budget = config!(max_width).saturating_sub(offset + 1);
+            let ty = &field.node.ty;
+            let ty_str = ty.rewrite(&self.get_context(), budget, offset)
+                           .unwrap_or_else(|| self.snippet(ty.span));
+            let result = format!("{}{}{}", vis, name, ty_str);
+
+            // Attributes go on the lines before the field, which forces the
+            // fields onto separate lines.
+            let attrs = &field.node.attrs;
+            if attrs.len() == 0 {
+                return result;
+            }
+            match self.rewrite_attrs(attrs, inner_indent) {
+                Some(attrs_str) => {
+                    format!("{}\n{}{}", attrs_str, make_indent(inner_indent), result)
+                }
+                // Skipped, keep the field as it is.
+                None => self.snippet(codemap::mk_sp(attrs[0].span.lo, field.span.hi)),
+            }
+        };
+        let items = itemize_list(self.codemap,
+                                 Vec::new(),
+                                 struct_def.fields.iter(),
+                                 ",",
+                                 "}",
+                                 |field| field_lo(field),
+                                 |field| field.span.hi,
+                                 |field| field_str(field),
+                                 span_after(span, "{", self.codemap),
+                                 next_span_start);
+
+        // Try everything on one line first, 6 = ` {  },`
+        let one_line_budget = config!(max_width).saturating_sub(self.block_indent +
+                                                                 used_width +
+                                                                 6);
+        let has_comments = items.iter().any(|item| {
+            item.pre_comment.is_some() || item.post_comment.is_some()
+        });
+        if !has_comments && !items.iter().any(|item| item.is_multiline()) {
+            let width = items.iter().map(|item| item.item.len() + 2).fold(0, |a, l| a + l);
+            if width <= one_line_budget + 2 {
+                let field_strs: Vec<_> = items.iter().map(|item| &item.item[..]).collect();
+                return format!(" {{ {} }}", field_strs.connect(", "));
+            }
+        }
+
+        let fmt = ListFormatting {
+            tactic: ListTactic::Vertical,
+            separator: ",",
+            trailing_separator: if config!(struct_trailing_comma) {
+                SeparatorTactic::Always
+            } else {
+                SeparatorTactic::Never
+            },
+            indent: inner_indent,
+            h_width: config!(max_width).saturating_sub(inner_indent),
+            v_width: config!(max_width).saturating_sub(inner_indent),
+            align_after: if config!(struct_field_align) {
+                Some(": ")
+            } else {
+                None
+            },
+            align_comments: config!(trailing_comment_align),
+        };
+
+        format!(" {{\n{}{}\n{}}}",
+                make_indent(inner_indent),
+                write_list(&items, &fmt),
+                make_indent(self.block_indent))
+    }
+
+    fn write_variant_list(&self, items: Vec<ListItem>, width: usize, indent: usize) -> String {
+        let fmt = ListFormatting {
+            tactic: ListTactic::HorizontalVertical,
+            separator: ",",
+            trailing_separator: SeparatorTactic::Never,
+            indent: indent,
+            h_width: width,
+            v_width: width,
+            align_after: None,
+            align_comments: config!(trailing_comment_align),
+        };
+
+        write_list(&items, &fmt)
+    }
+
+    // E.g., `pub struct Foo` or `enum Bar`.
+    fn format_header(&self,
+                     item_name: &str,
                      ident: ast::Ident,
                      vis: ast::Visibility)
         -> String
@@ -469,13 +792,460 @@ impl<'a> FmtVisitor<'a> {
             ""
         };
 
-        format!("{}struct {}", vis, &token::get_ident(ident))
+        format!("{}{}{}", vis, item_name, &token::get_ident(ident))
+    }
+
+    // Generics and where clause for a struct or enum, up to and including the
+    // opening brace.
+    fn format_generics(&self, generics: &ast::Generics, offset: usize, span_end: BytePos)
+        -> String
+    {
+        let mut result = self.rewrite_generics(generics, self.block_indent, span_end);
+        let where_offset = last_line_offset(&result, offset);
+        result.push_str(&self.format_where_and_brace(&generics.where_clause,
+                                                     where_offset,
+                                                     span_end));
+        result
+    }
+
+    // The where clause (if any) and opening brace of an item. offset is the
+    // column at which the where clause would start if it is kept on the same
+    // line as the rest of the header.
+    fn format_where_and_brace(&self,
+                              where_clause: &ast::WhereClause,
+                              offset: usize,
+                              span_end: BytePos)
+        -> String
+    {
+        // 2 = ` {`
+        let budget = config!(max_width).saturating_sub(offset + 2);
+        let mut result = self.rewrite_where_clause(where_clause,
+                                                   self.block_indent,
+                                                   budget,
+                                                   offset,
+                                                   span_end);
+
+        if self.newline_for_brace(config!(item_brace_style), where_clause) {
+            result.push('\n');
+            result.push_str(&make_indent(self.block_indent));
+            result.push('{');
+        } else {
+            result.push_str(" {");
+        }
+
+        result
+    }
+
+    // E.g., `pub unsafe trait Foo<T>: Bar + Baz where T: Qux {`.
+    pub fn rewrite_trait_header(&self,
+                                vis: ast::Visibility,
+                                unsafety: ast::Unsafety,
+                                ident: ast::Ident,
+                                generics: &ast::Generics,
+                                bounds: &[ast::TyParamBound],
+                                // Position of the opening brace.
+                                span_end: BytePos)
+        -> String
+    {
+        let mut result = String::with_capacity(128);
+        if vis == ast::Visibility::Public {
+            result.push_str("pub ");
+        }
+        if let ast::Unsafety::Unsafe = unsafety {
+            result.push_str("unsafe ");
+        }
+        result.push_str("trait ");
+        result.push_str(&token::get_ident(ident));
+
+        let generics_indent = self.block_indent + result.len();
+        let generics_end = match bounds.first() {
+            Some(bound) => span_for_ty_bound(bound).lo,
+            None => span_end,
+        };
+        result.push_str(&self.rewrite_generics(generics, generics_indent, generics_end));
+
+        // Supertraits.
+        if bounds.len() > 0 {
+            result.push_str(": ");
+            let offset = last_line_offset(&result, self.block_indent);
+            // 2 = ` {`
+            let budget = config!(max_width).saturating_sub(offset + 2);
+            result.push_str(&self.rewrite_bounds(bounds, budget, offset));
+        }
+
+        let where_offset = last_line_offset(&result, self.block_indent);
+        result.push_str(&self.format_where_and_brace(&generics.where_clause,
+                                                     where_offset,
+                                                     span_end));
+
+        result
+    }
+
+    // An associated type in a trait or impl, e.g., `type Item: Clone = u32;`.
+    pub fn rewrite_assoc_type(&self,
+                              vis: ast::Visibility,
+                              ident: ast::Ident,
+                              bounds: &[ast::TyParamBound],
+                              ty: Option<&ast::Ty>)
+        -> String
+    {
+        let mut result = String::with_capacity(64);
+        if vis == ast::Visibility::Public {
+            result.push_str("pub ");
+        }
+        result.push_str("type ");
+        result.push_str(&token::get_ident(ident));
+
+        if bounds.len() > 0 {
+            result.push_str(": ");
+            let offset = self.block_indent + result.len();
+            // 1 = `;`
+            let budget = config!(max_width).saturating_sub(offset + 1);
+            result.push_str(&self.rewrite_bounds(bounds, budget, offset));
+        }
+
+        if let Some(ty) = ty {
+            result.push_str(" = ");
+            let offset = last_line_offset(&result, self.block_indent);
+            // 1 = `;`
+            let budget = config!(max_width).saturating_sub(offset + 1);
+            let ty_str = ty.rewrite(&self.get_context(), budget, offset)
+                           .unwrap_or_else(|| self.snippet(ty.span));
+            result.push_str(&ty_str);
+        }
+
+        result.push(';');
+        result
+    }
+
+    // A const or static item, or an associated const, e.g., `const ID: u32 = 42;`
+    // or `pub static mut FOO: Foo = Foo::new();`. Associated consts in traits
+    // may have no initializer.
+    pub fn rewrite_static(&mut self,
+                          prefix: &str,
+                          vis: ast::Visibility,
+                          ident: ast::Ident,
+                          ty: &ast::Ty,
+                          mutability: ast::Mutability,
+                          expr: Option<&ast::Expr>)
+        -> String
+    {
+        let mut result = String::with_capacity(64);
+        if vis == ast::Visibility::Public {
+            result.push_str("pub ");
+        }
+        result.push_str(prefix);
+        result.push(' ');
+        if mutability == ast::Mutability::MutMutable {
+            result.push_str("mut ");
+        }
+        result.push_str(&token::get_ident(ident));
+        result.push_str(": ");
+
+        let offset = self.block_indent + result.len();
+        // 1 = `;`
+        let budget = config!(max_width).saturating_sub(offset + 1);
+        let ty_str = ty.rewrite(&self.get_context(), budget, offset)
+                       .unwrap_or_else(|| self.snippet(ty.span));
+        result.push_str(&ty_str);
+
+        if let Some(expr) = expr {
+            if !result.contains('\n') && result.len() < self.assign_width {
+                let padding = make_indent(self.assign_width - result.len());
+                result.push_str(&padding);
+            }
+            let rhs = self.rewrite_assign_rhs(&result, expr);
+            result.push_str(&rhs);
+        }
+
+        result.push(';');
+        result
+    }
+
+    // E.g., `pub type Foo<T> = Bar<T, u32>;`.
+    pub fn rewrite_type_alias(&self,
+                              vis: ast::Visibility,
+                              ident: ast::Ident,
+                              ty: &ast::Ty,
+                              generics: &ast::Generics,
+                              span: Span)
+        -> String
+    {
+        // FIXME where clauses on type aliases.
+        if generics.where_clause.predicates.len() > 0 {
+            return self.snippet(span);
+        }
+
+        let mut result = String::with_capacity(64);
+        if vis == ast::Visibility::Public {
+            result.push_str("pub ");
+        }
+        result.push_str("type ");
+        result.push_str(&token::get_ident(ident));
+
+        let generics_indent = self.block_indent + result.len();
+        result.push_str(&self.rewrite_generics(generics, generics_indent, ty.span.lo));
+        result.push_str(" =");
+
+        // 2 = ` ` and `;`
+        let offset = last_line_offset(&result, self.block_indent) + 1;
+        let budget = config!(max_width).saturating_sub(offset + 1);
+        match ty.rewrite(&self.get_context(), budget, offset) {
+            Some(ref ty_str) if first_line_width(ty_str) <= budget => {
+                result.push(' ');
+                result.push_str(ty_str);
+            }
+            _ => {
+                let indent = self.block_indent + config!(tab_spaces);
+                let budget = config!(max_width).saturating_sub(indent + 1);
+                let ty_str = ty.rewrite(&self.get_context(), budget, indent)
+                               .unwrap_or_else(|| self.snippet(ty.span));
+                result.push('\n');
+                result.push_str(&make_indent(indent));
+                result.push_str(&ty_str);
+            }
+        }
+
+        result.push(';');
+        result
+    }
+
+    // The width of the widest left-hand side of a run of const and static
+    // items, so that their `=`s line up. 0 if they can't be aligned.
+    pub fn static_assign_width(&mut self, items: &[P<ast::Item>]) -> usize {
+        let mut lhs_widths = Vec::with_capacity(items.len());
+        for item in items {
+            let (prefix, ty, mutability, expr) = match item.node {
+                ast::Item_::ItemConst(ref ty, ref expr) => {
+                    ("const", ty, ast::Mutability::MutImmutable, expr)
+                }
+                ast::Item_::ItemStatic(ref ty, mutability, ref expr) => {
+                    ("static", ty, mutability, expr)
+                }
+                _ => return 0,
+            };
+            // Without the expression, we get the left-hand side and a `;`.
+            let lhs = self.rewrite_static(prefix, item.vis, item.ident, ty, mutability, None);
+            let expr_str = self.snippet(expr.span);
+            if lhs.contains('\n') || expr_str.contains('\n') {
+                return 0;
+            }
+            lhs_widths.push((lhs.len() - 1, expr_str.len()));
+        }
+
+        self.assign_width(&lhs_widths)
+    }
+
+    // The formatted text before the `=` of a let statement with an initialiser,
+    // and the initialiser. None if the statement is anything else or if the
+    // text is not on one line.
+    pub fn let_lhs<'b>(&self, stmt: &'b ast::Stmt) -> Option<(String, &'b ast::Expr)> {
+        let local = match stmt.node {
+            ast::Stmt_::StmtDecl(ref decl, _) => {
+                match decl.node {
+                    ast::Decl_::DeclLocal(ref local) => local,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        let init = match local.init {
+            Some(ref init) => init,
+            None => return None,
+        };
+
+        let context = self.get_context();
+        // 4 = `let `
+        let offset = self.block_indent + 4;
+        let budget = config!(max_width).saturating_sub(offset);
+        let mut lhs = format!("let {}", try_opt!(local.pat.rewrite(&context, budget, offset)));
+        if let Some(ref ty) = local.ty {
+            // 2 = `: `
+            let offset = self.block_indent + lhs.len() + 2;
+            let budget = config!(max_width).saturating_sub(offset);
+            lhs.push_str(": ");
+            lhs.push_str(&try_opt!(ty.rewrite(&context, budget, offset)));
+        }
+        if lhs.contains('\n') {
+            return None;
+        }
+        Some((lhs, &**init))
+    }
+
+    // The end of the run of let statements with initialisers starting at
+    // start. Only a newline may separate the statements.
+    pub fn let_run_end(&self, stmts: &[P<ast::Stmt>], start: usize) -> usize {
+        let mut end = start + 1;
+        while end < stmts.len() && self.let_lhs(&stmts[end]).is_some() {
+            let gap = self.snippet(codemap::mk_sp(stmts[end - 1].span.hi, stmts[end].span.lo));
+            let gap = gap.trim_left_matches(';');
+            if gap.trim().len() > 0 || gap.chars().filter(|&c| c == '\n').count() != 1 {
+                break;
+            }
+            end += 1;
+        }
+
+        end
+    }
+
+    // The width of the widest left-hand side of a run of let statements, so
+    // that their `=`s line up. 0 if they can't be aligned.
+    pub fn let_assign_width(&self, stmts: &[P<ast::Stmt>]) -> usize {
+        let mut lhs_widths = Vec::with_capacity(stmts.len());
+        for stmt in stmts {
+            let (lhs, init) = match self.let_lhs(stmt) {
+                Some(lhs) => lhs,
+                None => return 0,
+            };
+            let init_str = self.snippet(init.span);
+            if init_str.contains('\n') {
+                return 0;
+            }
+            lhs_widths.push((lhs.len(), init_str.len()));
+        }
+
+        self.assign_width(&lhs_widths)
+    }
+
+    // The width to pad the left-hand sides of a run of assignments to, given
+    // the width of each left- and right-hand side, so that their `=`s line up.
+    // 0 if there are fewer than two, or if they would not fit once aligned.
+    fn assign_width(&self, lhs_widths: &[(usize, usize)]) -> usize {
+        if lhs_widths.len() < 2 {
+            return 0;
+        }
+
+        let assign_width = lhs_widths.iter().map(|&(lhs, _)| lhs).max().unwrap();
+        // 3 = ` = `, 1 = `;`
+        if lhs_widths.iter().any(|&(_, rhs)| {
+            self.block_indent + assign_width + 3 + rhs + 1 > config!(max_width)
+        }) {
+            return 0;
+        }
+
+        assign_width
+    }
+
+    // The ` = expr` part of an item, where lhs is everything before the `=`.
+    // If the expression does not fit on the same line as the lhs, then it is
+    // moved to the next line and indented.
+    fn rewrite_assign_rhs(&mut self, lhs: &str, expr: &ast::Expr) -> String {
+        // 3 = ` = `, 1 = `;`
+        let offset = last_line_offset(lhs, self.block_indent) + 3;
+        let budget = config!(max_width).saturating_sub(offset + 1);
+        match expr.rewrite(&self.get_context(), budget, offset) {
+            Some(ref expr_str) if first_line_width(expr_str) <= budget => {
+                return format!(" = {}", expr_str);
+            }
+            _ => {}
+        }
+
+        let indent = self.block_indent + config!(tab_spaces);
+        let budget = config!(max_width).saturating_sub(indent + 1);
+        let expr_str = expr.rewrite(&self.get_context(), budget, indent)
+                           .unwrap_or_else(|| self.snippet(expr.span));
+        format!(" =\n{}{}", make_indent(indent), expr_str)
+    }
+
+    // E.g., `unsafe impl<T: Foo> !Bar for Baz<T> where T: Qux {`.
+    pub fn rewrite_impl_header(&self,
+                               unsafety: ast::Unsafety,
+                               polarity: ast::ImplPolarity,
+                               generics: &ast::Generics,
+                               trait_ref: Option<&ast::TraitRef>,
+                               self_ty: &ast::Ty,
+                               // Position of the opening brace.
+                               span_end: BytePos)
+        -> String
+    {
+        let mut result = String::with_capacity(128);
+        if let ast::Unsafety::Unsafe = unsafety {
+            result.push_str("unsafe ");
+        }
+        result.push_str("impl");
+
+        let generics_indent = self.block_indent + result.len();
+        let generics_end = match trait_ref {
+            Some(trait_ref) => trait_ref.path.span.lo,
+            None => self_ty.span.lo,
+        };
+        result.push_str(&self.rewrite_generics(generics, generics_indent, generics_end));
+        result.push(' ');
+
+        if let Some(trait_ref) = trait_ref {
+            if polarity == ast::ImplPolarity::Negative {
+                result.push('!');
+            }
+            let offset = last_line_offset(&result, self.block_indent);
+            // 5 = ` for `
+            let budget = config!(max_width).saturating_sub(offset + 5);
+            let path_str = trait_ref.path
+                                    .rewrite(&self.get_context(), budget, offset)
+                                    .unwrap_or_else(|| self.snippet(trait_ref.path.span));
+            result.push_str(&path_str);
+            result.push_str(" for ");
+        }
+
+        let offset = last_line_offset(&result, self.block_indent);
+        // 2 = ` {`
+        let budget = config!(max_width).saturating_sub(offset + 2);
+        let ty_str = self_ty.rewrite(&self.get_context(), budget, offset)
+                            .unwrap_or_else(|| self.snippet(self_ty.span));
+        result.push_str(&ty_str);
+
+        let where_offset = last_line_offset(&result, self.block_indent);
+        result.push_str(&self.format_where_and_brace(&generics.where_clause,
+                                                     where_offset,
+                                                     span_end));
+
+        result
+    }
+
+    // The width of the widest `vis name:` of fields, so that the field types
+    // line up. 0 if the fields can't be aligned (e.g., they would not fit).
+    fn field_name_width(&self, fields: &[ast::StructField]) -> usize {
+        let mut heads = Vec::with_capacity(fields.len());
+        for field in fields {
+            match field.node.kind {
+                ast::StructFieldKind::NamedField(ident, vis) => {
+                    let vis = if vis == ast::Visibility::Public { "pub " } else { "" };
+                    // 1 = `:`
+                    heads.push((vis.len() + token::get_ident(ident).len() + 1, &field.node.ty));
+                }
+                ast::StructFieldKind::UnnamedField(_) => return 0,
+            }
+        }
+        if heads.len() < 2 {
+            return 0;
+        }
+
+        let name_width = heads.iter().map(|&(width, _)| width).max().unwrap();
+        // As in visit_field.
+        let budget = config!(ideal_width) - self.block_indent;
+        // 1 = space after the colon
+        let typ_offset = self.block_indent + name_width + 1;
+        for &(_, ty) in &heads {
+            let typ = match ty.rewrite(&self.get_context(),
+                                       config!(max_width).saturating_sub(typ_offset + 1),
+                                       typ_offset) {
+                Some(typ) => typ,
+                None => return 0,
+            };
+            // 1 = trailing comma
+            if typ.contains('\n') || typ_offset + typ.len() + 1 > budget {
+                return 0;
+            }
+        }
+
+        name_width
     }
 
     // Field of a struct
     fn visit_field(&mut self,
                    field: &ast::StructField,
                    last_field: bool,
+                   // The width to pad `vis name:` to, or 0.
+                   name_width: usize,
                    // These two args are for missing spans hacks.
                    struct_start: BytePos,
                    struct_snippet: &str)
@@ -497,23 +1267,45 @@ impl<'a> FmtVisitor<'a> {
                 ""
             }
         };
-        let typ = pprust::ty_to_string(&field.node.ty);
-
         let mut field_str = match name {
             Some(name) => {
                 let budget = config!(ideal_width) - self.block_indent;
-                // 3 is being conservative and assuming that there will be a trailing comma.
-                if self.block_indent + vis.len() + name.len() + typ.len() + 3 > budget {
-                    format!("{}{}:\n{}{}",
-                            vis,
-                            name,
-                            &make_indent(self.block_indent + config!(tab_spaces)),
-                            typ)
-                } else {
-                    format!("{}{}: {}", vis, name, typ)
+                // 1 = `:`
+                let padding = make_indent(name_width.saturating_sub(vis.len() + name.len() + 1));
+                // 2 = `: `
+                let typ_offset = self.block_indent + vis.len() + name.len() + padding.len() + 2;
+                // 1 = trailing comma
+                let typ = field.node.ty.rewrite(&self.get_context(),
+                                                config!(max_width).saturating_sub(typ_offset + 1),
+                                                typ_offset);
+                match typ {
+                    // 3 is being conservative and assuming that there will be a trailing comma.
+                    Some(ref typ) if !typ.contains('\n') &&
+                                     typ_offset + typ.len() + 1 <= budget => {
+                        format!("{}{}:{} {}", vis, name, padding, typ)
+                    }
+                    _ => {
+                        let typ_indent = self.block_indent + config!(tab_spaces);
+                        let typ = field.node
+                                       .ty
+                                       .rewrite(&self.get_context(),
+                                                config!(max_width).saturating_sub(typ_indent + 1),
+                                                typ_indent)
+                                       .unwrap_or_else(|| self.snippet(field.node.ty.span));
+                        format!("{}{}:\n{}{}", vis, name, &make_indent(typ_indent), typ)
+                    }
                 }
             }
-            None => format!("{}{}", vis, typ),
+            None => {
+                let typ_offset = self.block_indent + vis.len();
+                let typ = field.node
+                               .ty
+                               .rewrite(&self.get_context(),
+                                        config!(max_width).saturating_sub(typ_offset + 1),
+                                        typ_offset)
+                               .unwrap_or_else(|| self.snippet(field.node.ty.span));
+                format!("{}{}", vis, typ)
+            }
         };
         if !last_field || config!(struct_trailing_comma) {
             field_str.push(',');
@@ -531,119 +1323,273 @@ impl<'a> FmtVisitor<'a> {
         self.last_pos = hi + BytePos(comma_pos as u32 + 1);
     }
 
-    fn rewrite_generics(&self, generics: &ast::Generics, indent: usize, span_end: BytePos) -> String {
-        // FIXME convert bounds to where clauses where they get too big or if
-        // there is a where clause at all.
-        let mut result = String::new();
-        let lifetimes: &[_] = &generics.lifetimes;
-        let tys: &[_] = &generics.ty_params;
-        if lifetimes.len() + tys.len() == 0 {
-            return result;
-        }
-
-        let budget = config!(max_width) - indent - 2;
-        // TODO might need to insert a newline if the generics are really long
-        result.push('<');
-
-        // Strings for the generics.
-        let lt_strs = lifetimes.iter().map(|l| self.rewrite_lifetime_def(l));
-        let ty_strs = tys.iter().map(|ty| self.rewrite_ty_param(ty));
-
-        // Extract comments between generics.
-        let lt_spans = lifetimes.iter().map(|l| {
-            let hi = if l.bounds.len() == 0 {
-                l.lifetime.span.hi
-            } else {
-                l.bounds[l.bounds.len() - 1].span.hi
-            };
-            codemap::mk_sp(l.lifetime.span.lo, hi)
-        });
-        let ty_spans = tys.iter().map(span_for_ty_param);
-        let comments = self.make_comments_for_list(Vec::new(),
-                                                   lt_spans.chain(ty_spans),
-                                                   ",",
-                                                   ">",
-                                                   |sp| sp.lo,
-                                                   |sp| sp.hi,
-                                                   span_end);
-
-        // If there are // comments, keep them multi-line.
-        let mut list_tactic = ListTactic::HorizontalVertical;
-        if comments.iter().any(|c| c.contains("//")) {
-            list_tactic = ListTactic::Vertical;
-        }
-
-        let generics_strs: Vec<_> = lt_strs.chain(ty_strs).zip(comments.into_iter()).collect();
-        let fmt = ListFormatting {
-            tactic: list_tactic,
-            separator: ",",
-            trailing_separator: SeparatorTactic::Never,
-            indent: indent + 1,
-            h_width: budget,
-            v_width: budget,
-        };
-        result.push_str(&write_list(&generics_strs, &fmt));
-
-        result.push('>');
-
-        result
+    fn rewrite_generics(&self, generics: &ast::Generics, indent: usize, span_end: BytePos) -> String
+    {
+        rewrite_generics(&self.get_context(),
+                         generics,
+                         config!(max_width).saturating_sub(indent),
+                         indent,
+                         span_end).unwrap_or_else(|| pprust::generics_to_string(generics))
     }
 
+    // The where clause of an item at indent, including the leading newline or
+    // space. width and offset describe the rest of the line the item's header
+    // ends on, in case the where clause can be kept on that line.
     fn rewrite_where_clause(&self,
                             where_clause: &ast::WhereClause,
                             indent: usize,
+                            width: usize,
+                            offset: usize,
                             span_end: BytePos)
         -> String
     {
-        let mut result = String::new();
         if where_clause.predicates.len() == 0 {
-            return result;
+            return String::new();
+        }
+
+        // 7 = ` where `
+        if config!(where_single_line) && width > 7 {
+            let budget = width - 7;
+            let where_items = self.where_pred_items(where_clause, budget, offset + 7, span_end);
+            let fmt = ListFormatting {
+                tactic: ListTactic::Horizontal,
+                separator: ",",
+                // where_trailing_comma is only for the multi-line form.
+                trailing_separator: SeparatorTactic::Never,
+                indent: offset + 7,
+                h_width: budget,
+                v_width: budget,
+                align_after: None,
+                align_comments: false,
+            };
+            let preds_str = write_list(&where_items, &fmt);
+            // A line comment will have forced the list on to several lines.
+            if !preds_str.contains('\n') && preds_str.len() <= budget {
+                return format!(" where {}", preds_str);
+            }
         }
 
+        let mut result = String::new();
         result.push('\n');
-        result.push_str(&make_indent(indent + 4));
-        result.push_str("where ");
-
-        let comments = self.make_comments_for_list(Vec::new(),
-                                                   where_clause.predicates.iter(),
-                                                   ",",
-                                                   "{",
-                                                   |pred| span_for_where_pred(pred).lo,
-                                                   |pred| span_for_where_pred(pred).hi,
-                                                   span_end);
 
-        let where_strs: Vec<_> = where_clause.predicates.iter()
-                                                        .map(|p| (self.rewrite_pred(p)))
-                                                        .zip(comments.into_iter())
-                                                        .collect();
+        // With block indentation, `where` goes at the indentation of the item
+        // (whatever where_indent is) and the predicates go on the lines after
+        // it, indented one level.
+        let pred_indent = match config!(indent_style) {
+            IndentStyle::Visual => {
+                let where_indent = where_indent(indent);
+                result.push_str(&make_indent(where_indent));
+                result.push_str("where ");
+                // 6 = `where `
+                where_indent + 6
+            }
+            IndentStyle::Block => {
+                let pred_indent = indent + config!(tab_spaces);
+                result.push_str(&make_indent(indent));
+                result.push_str("where\n");
+                result.push_str(&make_indent(pred_indent));
+                pred_indent
+            }
+        };
+
+        let budget = (config!(ideal_width) + config!(leeway)).saturating_sub(pred_indent);
+        let where_items = self.where_pred_items(where_clause, budget, pred_indent, span_end);
 
-        let budget = config!(ideal_width) + config!(leeway) - indent - 10;
         let fmt = ListFormatting {
-            tactic: ListTactic::Vertical,
+            tactic: config!(where_pred_tactic),
             separator: ",",
-            trailing_separator: SeparatorTactic::Never,
-            indent: indent + 10,
+            trailing_separator: config!(where_trailing_comma),
+            indent: pred_indent,
             h_width: budget,
             v_width: budget,
+            align_after: None,
+            align_comments: config!(trailing_comment_align),
         };
-        result.push_str(&write_list(&where_strs, &fmt));
+        result.push_str(&write_list(&where_items, &fmt));
 
         result
     }
 
-    fn rewrite_return(&self, ret: &ast::FunctionRetTy) -> String {
-        match *ret {
-            ast::FunctionRetTy::DefaultReturn(_) => String::new(),
-            ast::FunctionRetTy::NoReturn(_) => "-> !".to_owned(),
-            ast::FunctionRetTy::Return(ref ty) => "-> ".to_owned() + &pprust::ty_to_string(ty),
+    fn where_pred_items(&self,
+                        where_clause: &ast::WhereClause,
+                        width: usize,
+                        offset: usize,
+                        span_end: BytePos)
+        -> Vec<ListItem>
+    {
+        itemize_list(self.codemap,
+                     Vec::new(),
+                     where_clause.predicates.iter(),
+                     ",",
+                     "{",
+                     |pred| span_for_where_pred(pred).lo,
+                     |pred| span_for_where_pred(pred).hi,
+                     |pred| {
+                         pred.rewrite(&self.get_context(), width, offset)
+                             .unwrap_or_else(|| self.snippet(span_for_where_pred(pred)))
+                     },
+                     span_for_where_pred(&where_clause.predicates[0]).lo,
+                     span_end)
+    }
+
+    fn rewrite_fn_input(&self, arg: &ast::Arg, width: usize, offset: usize) -> String {
+        let context = self.get_context();
+        let pat_str = arg.pat.rewrite(&context, width, offset)
+                             .unwrap_or_else(|| self.snippet(arg.pat.span));
+        // 2 = `: `
+        let overhead = pat_str.len() + 2;
+        let ty_str = arg.ty.rewrite(&context, width.saturating_sub(overhead), offset + overhead)
+                           .unwrap_or_else(|| self.snippet(arg.ty.span));
+        format!("{}: {}", pat_str, ty_str)
+    }
+
+    // Bounds of a trait or associated type, e.g., `Clone + Send`.
+    fn rewrite_bounds(&self, bounds: &[ast::TyParamBound], width: usize, offset: usize) -> String
+    {
+        let bound_strs = bounds.iter()
+                               .map(|b| {
+                                   b.rewrite(&self.get_context(), width, offset)
+                                    .unwrap_or_else(|| self.snippet(span_for_ty_bound(b)))
+                               })
+                               .collect();
+        rewrite_bound_list(bound_strs, width, offset)
+    }
+}
+
+impl Rewrite for ast::Generics {
+    fn rewrite(&self, context: &RewriteContext, width: usize, offset: usize) -> Option<String> {
+        // Comments after the last parameter are not part of the generics.
+        let span_end = match generic_spans(self).last() {
+            Some(span) => span.hi,
+            None => return Some(String::new()),
+        };
+        rewrite_generics(context, self, width, offset, span_end)
+    }
+}
+
+// span_end is the end of any comments after the last parameter.
+fn rewrite_generics(context: &RewriteContext,
+                    generics: &ast::Generics,
+                    width: usize,
+                    offset: usize,
+                    span_end: BytePos)
+    -> Option<String>
+{
+    // FIXME convert bounds to where clauses where they get too big or if
+    // there is a where clause at all.
+    if generics.lifetimes.len() + generics.ty_params.len() == 0 {
+        return Some(String::new());
+    }
+
+    match config!(indent_style) {
+        // TODO might need to insert a newline if the generics are really long
+        IndentStyle::Visual => {
+            rewrite_generics_list(context, generics, width, offset, span_end, ListLayout::Visual)
+        }
+        IndentStyle::Block => {
+            let result = rewrite_generics_list(context,
+                                               generics,
+                                               width,
+                                               offset,
+                                               span_end,
+                                               ListLayout::Visual);
+            match result {
+                Some(ref result) if fits_in(result, width) => return Some(result.clone()),
+                _ => {}
+            }
+            rewrite_generics_list(context,
+                                  generics,
+                                  width,
+                                  offset,
+                                  span_end,
+                                  ListLayout::OnePerLine)
+        }
+    }
+}
+
+fn rewrite_generics_list(context: &RewriteContext,
+                         generics: &ast::Generics,
+                         width: usize,
+                         offset: usize,
+                         span_end: BytePos,
+                         layout: ListLayout)
+    -> Option<String>
+{
+    let (tactic, indent, budget) = match layout {
+        // 2 = `<` and `>`
+        ListLayout::Visual => (visual_tactic(), offset + 1, try_opt!(width.checked_sub(2))),
+        ListLayout::Block | ListLayout::OnePerLine => {
+            let indent = context.block_indent + config!(tab_spaces);
+            // 1 = `,`
+            (ListTactic::Vertical, indent, try_opt!(config!(max_width).checked_sub(indent + 1)))
         }
+    };
+    let inner_context = block_context(context, layout, indent);
+
+    // Strings for the generics.
+    let lt_strs = generics.lifetimes.iter().map(|l| l.rewrite(&inner_context, budget, indent));
+    let ty_strs = generics.ty_params.iter().map(|ty| ty.rewrite(&inner_context, budget, indent));
+    let generic_strs = try_opt!(lt_strs.chain(ty_strs).collect::<Option<Vec<_>>>());
+
+    // Extract comments between generics.
+    let spans = generic_spans(generics);
+    let mut items = itemize_list(context.codemap,
+                                 Vec::new(),
+                                 spans.iter(),
+                                 ",",
+                                 ">",
+                                 |sp| sp.lo,
+                                 |sp| sp.hi,
+                                 |_| String::new(),
+                                 spans[0].lo,
+                                 span_end);
+    for (item, s) in items.iter_mut().zip(generic_strs.into_iter()) {
+        item.item = s;
     }
 
-    // TODO we farm this out, but this could spill over the column limit, so we ought to handle it properly
-    fn rewrite_fn_input(&self, arg: &ast::Arg) -> String {
-        format!("{}: {}",
-                pprust::pat_to_string(&arg.pat),
-                pprust::ty_to_string(&arg.ty))
+    let fmt = ListFormatting {
+        tactic: tactic,
+        separator: ",",
+        trailing_separator: SeparatorTactic::Never,
+        indent: indent,
+        h_width: budget,
+        v_width: budget,
+        align_after: None,
+        align_comments: config!(trailing_comment_align),
+    };
+    let generics_str = write_list(&items, &fmt);
+
+    Some(wrap_list(context, "", ("<", ">"), &generics_str, layout, indent))
+}
+
+// The spans of the lifetimes and type parameters of generics, in order.
+fn generic_spans(generics: &ast::Generics) -> Vec<Span> {
+    let lt_spans = generics.lifetimes.iter().map(|l| {
+        let hi = if l.bounds.len() == 0 {
+            l.lifetime.span.hi
+        } else {
+            l.bounds[l.bounds.len() - 1].span.hi
+        };
+        codemap::mk_sp(l.lifetime.span.lo, hi)
+    });
+    let ty_spans = generics.ty_params.iter().map(span_for_ty_param);
+    lt_spans.chain(ty_spans).collect()
+}
+
+pub fn rewrite_return(context: &RewriteContext,
+                      ret: &ast::FunctionRetTy,
+                      width: usize,
+                      offset: usize)
+    -> Option<String>
+{
+    match *ret {
+        ast::FunctionRetTy::DefaultReturn(_) => Some(String::new()),
+        ast::FunctionRetTy::NoReturn(_) => Some("-> !".to_owned()),
+        ast::FunctionRetTy::Return(ref ty) => {
+            // 3 = `-> `
+            let budget = try_opt!(width.checked_sub(3));
+            Some(format!("-> {}", try_opt!(ty.rewrite(context, budget, offset + 3))))
+        }
     }
 }
 
@@ -671,6 +1617,21 @@ fn span_for_ty_param(ty: &ast::TyParam) -> Span {
     codemap::mk_sp(lo, hi)
 }
 
+fn span_for_ty_bound(bound: &ast::TyParamBound) -> Span {
+    match *bound {
+        ast::TyParamBound::TraitTyParamBound(ref ptr, _) => ptr.span,
+        ast::TyParamBound::RegionTyParamBound(ref l) => l.span,
+    }
+}
+
+// The indent of the `where` keyword for an item at indent.
+fn where_indent(indent: usize) -> usize {
+    match config!(where_indent) {
+        BlockIndentStyle::Inherit => indent,
+        BlockIndentStyle::Tabbed => indent + config!(tab_spaces),
+    }
+}
+
 fn span_for_where_pred(pred: &ast::WherePredicate) -> Span {
     match *pred {
         ast::WherePredicate::BoundPredicate(ref p) => p.span,
@@ -678,3 +1639,8 @@ fn span_for_where_pred(pred: &ast::WherePredicate) -> Span {
         ast::WherePredicate::EqPredicate(ref p) => p.span,
     }
 }
+
+// The start of a struct field, including its attributes.
+fn field_lo(field: &ast::StructField) -> BytePos {
+    field.node.attrs.first().map_or(field.span.lo, |attr| attr.span.lo)
+}
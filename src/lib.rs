@@ -23,6 +23,7 @@ extern crate log;
 extern crate getopts;
 extern crate rustc;
 extern crate rustc_driver;
+extern crate rustc_resolve;
 extern crate syntax;
 extern crate rustc_serialize;
 
@@ -31,32 +32,42 @@ extern crate strings;
 use rustc::session::Session;
 use rustc::session::config as rustc_config;
 use rustc::session::config::Input;
+use rustc::ast_map;
+use rustc::lint;
 use rustc_driver::{driver, CompilerCalls, Compilation};
 
 use rustc_serialize::{Decodable, Decoder};
 
 use syntax::ast;
-use syntax::codemap::CodeMap;
+use syntax::codemap::{CodeMap, Span};
 use syntax::diagnostics;
+use syntax::parse;
 use syntax::visit;
 
 use std::path::PathBuf;
 use std::collections::HashMap;
+use std::cell::RefCell;
+use std::rc::Rc;
 
 use changes::ChangeSet;
 use visitor::FmtVisitor;
 
 #[macro_use]
 mod config;
+#[macro_use]
+mod utils;
 mod changes;
 mod visitor;
 mod items;
 mod missed_spans;
 mod lists;
-mod utils;
 mod types;
+mod patterns;
 mod expr;
 mod imports;
+mod macros;
+mod comment;
+mod rewrite;
 
 const MIN_STRING: usize = 10;
 // When we get scoped annotations, we should have rustfmt::skip.
@@ -97,7 +108,7 @@ pub enum BraceStyle {
     AlwaysNextLine,
     PreferSameLine,
     // Prefer same line except where there is a where clause, in which case force
-    // the brace to the next line.
+    // the brace to the next line (unless where_brace_newline is false).
     SameLineWhere,
 }
 
@@ -113,6 +124,27 @@ impl Decodable for BraceStyle {
     }
 }
 
+// How to indent a block-like construct, such as a where clause, relative to
+// the item it belongs to.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum BlockIndentStyle {
+    // Same indent as the item.
+    Inherit,
+    // One level deeper than the item.
+    Tabbed,
+}
+
+impl Decodable for BlockIndentStyle {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Self, D::Error> {
+        let s = try!(d.read_str());
+        match &*s {
+            "Inherit" => Ok(BlockIndentStyle::Inherit),
+            "Tabbed" => Ok(BlockIndentStyle::Tabbed),
+            _ => Err(d.error("Bad variant")),
+        }
+    }
+}
+
 // How to indent a function's return type.
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum ReturnIndent {
@@ -134,23 +166,220 @@ impl Decodable for ReturnIndent {
     }
 }
 
-// Formatting which depends on the AST.
-fn fmt_ast<'a>(krate: &ast::Crate, codemap: &'a CodeMap) -> ChangeSet<'a> {
+// How to write doc comments.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum DocCommentStyle {
+    // Leave doc comments as they are.
+    Preserve,
+    // `///` and `//!` comments.
+    Line,
+    // `/** */` and `/*! */` comments.
+    Block,
+}
+
+impl Decodable for DocCommentStyle {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Self, D::Error> {
+        let s = try!(d.read_str());
+        match &*s {
+            "Preserve" => Ok(DocCommentStyle::Preserve),
+            "Line" => Ok(DocCommentStyle::Line),
+            "Block" => Ok(DocCommentStyle::Block),
+            _ => Err(d.error("Bad variant")),
+        }
+    }
+}
+
+// How to merge or split use items.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ImportsGranularity {
+    // Leave use items as they are.
+    Preserve,
+    // Merge imports from the same module, e.g., `use a::b; use a::c;` becomes
+    // `use a::{b, c};`.
+    Merge,
+    // One use item per import.
+    Item,
+    // Merge imports with a common first segment, nesting lists where paths
+    // share a prefix, e.g., `use a::{b::{c, d}, e};`. Not supported: a list
+    // item must be a single name for this compiler, so we could not parse our
+    // own output. Use items are left as they are, as for Preserve.
+    Nested,
+}
+
+impl Decodable for ImportsGranularity {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Self, D::Error> {
+        let s = try!(d.read_str());
+        match &*s {
+            "Preserve" => Ok(ImportsGranularity::Preserve),
+            "Merge" => Ok(ImportsGranularity::Merge),
+            "Item" => Ok(ImportsGranularity::Item),
+            "Nested" => Ok(ImportsGranularity::Nested),
+            _ => Err(d.error("Bad variant")),
+        }
+    }
+}
+
+// How to indent lists which don't fit on one line, such as the arguments of a
+// call or a function.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum IndentStyle {
+    // Line up the items with the first one, which follows the opening
+    // delimiter.
+    Visual,
+    // End the line after the opening delimiter, indent the items one level
+    // and put the closing delimiter on a line of its own. A where clause
+    // starts on a new line at the item's indentation, with its predicates on
+    // the lines after `where`.
+    Block,
+}
+
+impl Decodable for IndentStyle {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Self, D::Error> {
+        let s = try!(d.read_str());
+        match &*s {
+            "Visual" => Ok(IndentStyle::Visual),
+            "Block" => Ok(IndentStyle::Block),
+            _ => Err(d.error("Bad variant")),
+        }
+    }
+}
+
+// Formatting which depends on the AST. unused_imports are the spans of imports
+// to remove. Also returns the positions of glob imports, if report_glob_imports
+// is set, as (file name, line).
+fn fmt_ast<'a>(krate: &ast::Crate,
+               codemap: &'a CodeMap,
+               unused_imports: Vec<Span>)
+               -> (ChangeSet<'a>, Vec<(String, usize)>) {
     let mut visitor = FmtVisitor::from_codemap(codemap);
+    visitor.unused_imports = unused_imports;
     visit::walk_crate(&mut visitor, krate);
     let files = codemap.files.borrow();
     if let Some(last) = files.last() {
         visitor.format_missing(last.end_pos);
     }
 
-    visitor.changes
+    (visitor.changes, visitor.glob_imports)
+}
+
+// Formats a string of Rust source as if it were a complete file. Returns None
+// if the source does not parse.
+fn format_snippet(source: &str) -> Option<String> {
+    let parse_session = utils::silent_parse_session();
+    let mut parser = parse::new_parser_from_source_str(&parse_session,
+                                                       Vec::new(),
+                                                       "<snippet>".to_owned(),
+                                                       source.to_owned());
+    let krate = match parser.parse_crate_mod() {
+        Ok(krate) => krate,
+        Err(_) => return None,
+    };
+    if parse_session.span_diagnostic.handler.err_count() > 0 {
+        return None;
+    }
+
+    let (changes, _) = fmt_ast(&krate, parse_session.codemap(), Vec::new());
+    let result = changes.text().next().map(|(_, text)| text.to_string());
+    result
+}
+
+// Formats a fragment of Rust code which is either a sequence of items or the
+// body of a function (e.g., a macro transcriber or a doc test). Returns None if
+// the code does not parse.
+fn format_code(source: &str) -> Option<String> {
+    let source = source.trim();
+    if utils::looks_like_items(source) {
+        return format_snippet(source).map(|s| s.trim().to_owned());
+    }
+
+    // Format statements and expressions as the body of a function, then strip
+    // the function.
+    let wrapped = format!("fn __rustfmt_wrapper() {{\n{}\n}}", source);
+    let formatted = match format_snippet(&wrapped) {
+        Some(formatted) => formatted,
+        None => return None,
+    };
+    let lines: Vec<_> = formatted.trim().lines().collect();
+    if lines.len() < 2 {
+        return None;
+    }
+
+    let indent = utils::make_indent(config!(tab_spaces));
+    let mut result = String::with_capacity(formatted.len());
+    for line in &lines[1..lines.len() - 1] {
+        if line.starts_with(&indent) {
+            result.push_str(&line[indent.len()..]);
+        } else {
+            result.push_str(line.trim_left());
+        }
+        result.push('\n');
+    }
+
+    Some(result.trim().to_owned())
+}
+
+// Run name resolution over the expanded crate and return the spans of the
+// imports which it finds are unused and which we may remove from the parsed
+// crate.
+fn find_unused_imports(session: &Session,
+                       ast_map: &ast_map::Map,
+                       krate: &ast::Crate)
+                       -> Vec<Span> {
+    // The last lint option for unused imports on the command line wins.
+    let allowed = session.opts
+                         .lint_opts
+                         .iter()
+                         .rev()
+                         .find(|&&(ref name, _)| *name == "unused_imports" || *name == "unused")
+                         .map_or(false, |&(_, level)| level == lint::Allow);
+    if allowed {
+        return Vec::new();
+    }
+
+    rustc_resolve::resolve_crate(session, ast_map, rustc_resolve::MakeGlobMap::No);
+
+    // Resolution reports unused imports as lints, which are stored in the
+    // session until the lint checking pass (which we never reach). So lint
+    // levels have not been applied, and we must check those ourselves.
+    let removable = imports::removable_imports(krate);
+    let unused_imports = lint::LintId::of(lint::builtin::UNUSED_IMPORTS);
+    session.lints
+           .borrow()
+           .values()
+           .flat_map(|lints| lints.iter())
+           .filter(|&&(lint_id, _, _)| lint_id == unused_imports)
+           .map(|&(_, span, _)| span)
+           .filter(|span| removable.contains(span))
+           .collect()
+}
+
+fn format_crate(krate: &ast::Crate,
+                codemap: &CodeMap,
+                unused_imports: Vec<Span>,
+                write_mode: WriteMode) {
+    let (mut changes, glob_imports) = fmt_ast(krate, codemap, unused_imports);
+    // For some reason, the codemap does not include terminating newlines
+    // so we must add one on for each file. This is sad.
+    changes.append_newlines();
+    fmt_lines(&mut changes, &glob_imports);
+
+    let result = changes.write_all_files(write_mode);
+
+    match result {
+        Err(msg) => println!("Error writing files: {}", msg),
+        Ok(result) => {
+            if let WriteMode::Return(callback) = write_mode {
+                callback(result);
+            }
+        }
+    }
 }
 
 // Formatting done on a char by char or line by line basis.
 // TODO warn on TODOs and FIXMEs without an issue number
 // TODO warn on bad license
 // TODO other stuff for parity with make tidy
-fn fmt_lines(changes: &mut ChangeSet) {
+fn fmt_lines(changes: &mut ChangeSet, glob_imports: &[(String, usize)]) {
     let mut truncate_todo = Vec::new();
 
     // Iterate over the chars in the change set.
@@ -200,6 +429,10 @@ fn fmt_lines(changes: &mut ChangeSet) {
             // TODO store the error rather than reporting immediately.
             println!("Rustfmt left trailing whitespace at {}:{} (sorry)", f, l);
         }
+
+        for &(_, l) in glob_imports.iter().filter(|&&(ref file, _)| file == f) {
+            println!("Rustfmt: {}:{}: glob import could be expanded to an explicit list", f, l);
+        }
     }
 
     for (f, l) in truncate_todo {
@@ -257,26 +490,34 @@ impl<'a> CompilerCalls<'a> for RustFmtCalls {
     fn build_controller(&mut self, _: &Session) -> driver::CompileController<'a> {
         let write_mode = self.write_mode;
         let mut control = driver::CompileController::basic();
-        control.after_parse.stop = Compilation::Stop;
+        if !config!(remove_unused_imports) {
+            control.after_parse.stop = Compilation::Stop;
+            control.after_parse.callback = Box::new(move |state| {
+                format_crate(state.krate.unwrap(),
+                             state.session.codemap(),
+                             Vec::new(),
+                             write_mode);
+            });
+            return control;
+        }
+
+        // Name resolution needs the expanded crate, but we format the crate as
+        // it was parsed, so keep a copy of that.
+        let parsed_krate = Rc::new(RefCell::new(None));
+        let parsed_krate_ref = parsed_krate.clone();
         control.after_parse.callback = Box::new(move |state| {
-            let krate = state.krate.unwrap();
-            let codemap = state.session.codemap();
-            let mut changes = fmt_ast(krate, codemap);
-            // For some reason, the codemap does not include terminating newlines
-            // so we must add one on for each file. This is sad.
-            changes.append_newlines();
-            fmt_lines(&mut changes);
-
-            let result = changes.write_all_files(write_mode);
-
-            match result {
-                Err(msg) => println!("Error writing files: {}", msg),
-                Ok(result) => {
-                    if let WriteMode::Return(callback) = write_mode {
-                        callback(result);
-                    }
-                }
-            }
+            *parsed_krate_ref.borrow_mut() = Some(state.krate.unwrap().clone());
+        });
+        control.after_write_deps.stop = Compilation::Stop;
+        control.after_write_deps.callback = Box::new(move |state| {
+            let krate = parsed_krate.borrow();
+            let unused_imports = find_unused_imports(state.session,
+                                                     state.ast_map.unwrap(),
+                                                     krate.as_ref().unwrap());
+            format_crate(krate.as_ref().unwrap(),
+                         state.session.codemap(),
+                         unused_imports,
+                         write_mode);
         });
 
         control
@@ -290,6 +531,10 @@ impl<'a> CompilerCalls<'a> for RustFmtCalls {
 // default_config is a string of toml data to be used to configure rustfmt.
 pub fn run(args: Vec<String>, write_mode: WriteMode, default_config: &str) {
     config::set_config(default_config);
+    if config!(imports_granularity) == ImportsGranularity::Nested {
+        println!("Rustfmt can't nest imports (sorry), imports_granularity = \"Nested\" is \
+                  treated as \"Preserve\"");
+    }
 
     let mut call_ctxt = RustFmtCalls { input_path: None, write_mode: write_mode };
     rustc_driver::run_compiler(&args, &mut call_ctxt);
@@ -8,9 +8,14 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use utils::make_indent;
+use utils::{make_indent, last_line_offset};
+use comment::{rewrite_comment, find_uncommented, contains_line_comment, comment_len};
+
+use syntax::codemap::{self, CodeMap, BytePos};
 use rustc_serialize::{Decodable, Decoder};
 
+use IndentStyle;
+
 #[derive(Eq, PartialEq, Debug, Copy, Clone)]
 pub enum ListTactic {
     // One item per row.
@@ -30,7 +35,48 @@ pub enum SeparatorTactic {
     Vertical,
 }
 
+// The ways of laying out a delimited list, such as the arguments of a call,
+// in the order in which we try them.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum ListLayout {
+    // The items follow the opening delimiter and further rows line up with
+    // the first item.
+    Visual,
+    // The opening delimiter ends the line, all the items go on the next line,
+    // indented one level from the enclosing block, and the closing delimiter
+    // goes on its own line.
+    Block,
+    // Like Block, but with one item per line.
+    OnePerLine,
+}
+
+pub const LIST_LAYOUTS: &'static [ListLayout] = &[ListLayout::Visual,
+                                                  ListLayout::Block,
+                                                  ListLayout::OnePerLine];
+
+// The tactic for the visual layout of a list. With block indentation, lists
+// are only laid out visually if they fit on one line.
+pub fn visual_tactic() -> ListTactic {
+    match config!(indent_style) {
+        IndentStyle::Visual => ListTactic::HorizontalVertical,
+        IndentStyle::Block => ListTactic::Horizontal,
+    }
+}
+
 // TODO could use a macro for all these Decodable impls.
+impl Decodable for ListTactic {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Self, D::Error> {
+        let s = try!(d.read_str());
+        match &*s {
+            "Vertical" => Ok(ListTactic::Vertical),
+            "Horizontal" => Ok(ListTactic::Horizontal),
+            "HorizontalVertical" => Ok(ListTactic::HorizontalVertical),
+            "Mixed" => Ok(ListTactic::Mixed),
+            _ => Err(d.error("Bad variant")),
+        }
+    }
+}
+
 impl Decodable for SeparatorTactic {
     fn decode<D: Decoder>(d: &mut D) -> Result<Self, D::Error> {
         let s = try!(d.read_str());
@@ -53,17 +99,63 @@ pub struct ListFormatting<'a> {
     pub h_width: usize,
     // Available width if we layout vertically
     pub v_width: usize,
+    // In a vertical layout, pad each item after the first occurrence of this
+    // string (less any trailing whitespace) so that what follows it lines up,
+    // e.g., `a:   u32` and `bcd: u32`. Only used if every item fits.
+    pub align_after: Option<&'a str>,
+    // In a vertical layout, line up the comments which follow items.
+    pub align_comments: bool,
+}
+
+pub struct ListItem {
+    pub pre_comment: Option<String>,
+    pub item: String,
+    pub post_comment: Option<String>,
 }
 
-// Format a list of strings into a string.
+impl ListItem {
+    pub fn from_str<S: Into<String>>(s: S) -> ListItem {
+        ListItem {
+            pre_comment: None,
+            item: s.into(),
+            post_comment: None,
+        }
+    }
+
+    pub fn is_multiline(&self) -> bool {
+        self.item.contains('\n') ||
+        self.pre_comment.as_ref().map_or(false, |s| s.contains('\n')) ||
+        self.post_comment.as_ref().map_or(false, |s| s.contains('\n'))
+    }
+
+    // A line comment must be followed by a newline.
+    pub fn has_line_comment(&self) -> bool {
+        self.pre_comment.as_ref().map_or(false, |s| contains_line_comment(s)) ||
+        self.post_comment.as_ref().map_or(false, |s| contains_line_comment(s))
+    }
+
+    fn width(&self) -> usize {
+        // We'll put a space after a pre-comment and before a post-comment.
+        self.pre_comment.as_ref().map_or(0, |s| s.len() + 1) +
+        self.item.len() +
+        self.post_comment.as_ref().map_or(0, |s| s.len() + 1)
+    }
+}
+
+// Format a list of items into a string.
 // Precondition: all strings in items are trimmed.
-pub fn write_list<'b>(items: &[(String, String)], formatting: &ListFormatting<'b>) -> String {
+pub fn write_list<'b>(items: &[ListItem], formatting: &ListFormatting<'b>) -> String {
     if items.len() == 0 {
         return String::new();
     }
 
     let mut tactic = formatting.tactic;
 
+    // Nothing can follow a line comment on the same line.
+    if items.iter().any(|item| item.has_line_comment()) {
+        tactic = ListTactic::Vertical;
+    }
+
     // Conservatively overestimates because of the changing separator tactic.
     let sep_count = if formatting.trailing_separator != SeparatorTactic::Never {
         items.len()
@@ -79,7 +171,7 @@ pub fn write_list<'b>(items: &[(String, String)], formatting: &ListFormatting<'b
     if tactic == ListTactic::HorizontalVertical {
         debug!("write_list: total_width: {}, total_sep_len: {}, h_width: {}",
                total_width, total_sep_len, formatting.h_width);
-        tactic = if fits_single {
+        tactic = if fits_single && !items.iter().any(|item| item.is_multiline()) {
             ListTactic::Horizontal
         } else {
             ListTactic::Vertical
@@ -105,11 +197,38 @@ pub fn write_list<'b>(items: &[(String, String)], formatting: &ListFormatting<'b
     };
     let mut result = String::with_capacity(alloc_width);
 
+    let aligned_items = if tactic == ListTactic::Vertical {
+        align_items(items, formatting, sep_len)
+    } else {
+        None
+    };
+    let item_strs = aligned_items.unwrap_or_else(|| {
+        items.iter().map(|item| item.item.clone()).collect()
+    });
+
+    // The column at which comments after items start, if they are aligned.
+    let comment_offset = if tactic == ListTactic::Vertical && formatting.align_comments {
+        items.iter()
+             .zip(item_strs.iter())
+             .enumerate()
+             .filter(|&(_, (item, _))| item.post_comment.is_some())
+             .map(|(i, (_, item_str))| {
+                 let separate = i < items.len() - 1 || trailing_separator;
+                 let sep_width = if separate { sep_len } else { 0 };
+                 last_line_offset(item_str, formatting.indent) + sep_width + 1
+             })
+             .max()
+             .unwrap_or(0)
+    } else {
+        0
+    };
+
     let mut line_len = 0;
     let indent_str = &make_indent(formatting.indent);
-    for (i, &(ref item, ref comment)) in items.iter().enumerate() {
+    for (i, (item, item_str)) in items.iter().zip(item_strs.iter()).enumerate() {
         let first = i == 0;
-        let separate = i != items.len() - 1 || trailing_separator;
+        let last = i == items.len() - 1;
+        let separate = !last || trailing_separator;
 
         match tactic {
             ListTactic::Horizontal if !first => {
@@ -120,7 +239,7 @@ pub fn write_list<'b>(items: &[(String, String)], formatting: &ListFormatting<'b
                 result.push_str(indent_str);
             }
             ListTactic::Mixed => {
-                let mut item_width = item.len();
+                let mut item_width = item.width();
                 if separate {
                     item_width += sep_len;
                 }
@@ -141,30 +260,215 @@ pub fn write_list<'b>(items: &[(String, String)], formatting: &ListFormatting<'b
             _ => {}
         }
 
-        result.push_str(item);
+        // Pre-comments
+        if let Some(ref comment) = item.pre_comment {
+            let width = config!(max_width).saturating_sub(formatting.indent);
+            result.push_str(&format_list_comment(comment, width, formatting.indent));
+            if tactic == ListTactic::Vertical &&
+               (contains_line_comment(comment) || comment.contains('\n')) {
+                result.push('\n');
+                result.push_str(indent_str);
+            } else {
+                result.push(' ');
+            }
+        }
 
-        if tactic != ListTactic::Vertical && comment.len() > 0 {
-            if !comment.starts_with('\n') {
+        result.push_str(item_str);
+
+        // Post-comments
+        if tactic != ListTactic::Vertical {
+            if let Some(ref comment) = item.post_comment {
                 result.push(' ');
+                result.push_str(comment);
             }
-            result.push_str(comment);
         }
 
         if separate {
             result.push_str(formatting.separator);
         }
 
-        if tactic == ListTactic::Vertical && comment.len() > 0 {
-            if !comment.starts_with('\n') {
+        if tactic == ListTactic::Vertical {
+            if let Some(ref comment) = item.post_comment {
+                let mut offset = last_line_offset(item_str, formatting.indent) + 1;
+                if separate {
+                    offset += sep_len;
+                }
+                let padding = if comment_offset > offset && comment_offset < config!(max_width) {
+                    comment_offset - offset
+                } else {
+                    0
+                };
+                let offset = offset + padding;
+                let width = config!(max_width).saturating_sub(offset);
                 result.push(' ');
+                result.push_str(&make_indent(padding));
+                result.push_str(&format_list_comment(comment, width, offset));
+
+                // Don't comment out whatever follows the list.
+                if last && contains_line_comment(comment) {
+                    result.push('\n');
+                    result.push_str(indent_str);
+                }
             }
-            result.push_str(comment);
         }
     }
 
     result
 }
 
+// The items of a vertical list, padded according to formatting.align_after, or
+// None if they should not be aligned.
+fn align_items(items: &[ListItem], formatting: &ListFormatting, sep_len: usize)
+    -> Option<Vec<String>>
+{
+    let pat = match formatting.align_after {
+        Some(pat) => pat,
+        None => return None,
+    };
+    if items.iter().any(|item| item.item.contains('\n')) {
+        return None;
+    }
+
+    let pad_points: Vec<_> = items.iter()
+                                  .map(|item| {
+                                      find_uncommented(&item.item, pat)
+                                          .map(|i| i + pat.trim_right().len())
+                                  })
+                                  .collect();
+    if pad_points.iter().filter(|p| p.is_some()).count() < 2 {
+        return None;
+    }
+    let column = pad_points.iter().filter_map(|p| *p).max().unwrap();
+
+    let mut result = Vec::with_capacity(items.len());
+    for (item, pad_point) in items.iter().zip(pad_points.into_iter()) {
+        let item_str = match pad_point {
+            Some(i) => {
+                format!("{}{}{}", &item.item[..i], make_indent(column - i), &item.item[i..])
+            }
+            None => item.item.clone(),
+        };
+        if item_str.len() + sep_len > formatting.v_width {
+            return None;
+        }
+        result.push(item_str);
+    }
+
+    Some(result)
+}
+
+// A comment in a list may span several lines (e.g., several line comments), in
+// which case the later lines are aligned with the first.
+fn format_list_comment(comment: &str, width: usize, offset: usize) -> String {
+    if comment.starts_with("/*") && comment_len(comment) == Some(comment.len()) {
+        return rewrite_comment(comment, width, offset);
+    }
+
+    comment.lines()
+           .map(|l| rewrite_comment(l.trim(), width, offset))
+           .collect::<Vec<_>>()
+           .connect(&format!("\n{}", make_indent(offset)))
+}
+
+// Turn a sequence of AST nodes into list items, attaching any comments
+// between them. A comment on the same line as an item (after its separator)
+// belongs to that item, a comment on a line of its own belongs to the following
+// item. prev_span_end is the position after the list's opening delimiter and
+// next_span_start is the start of whatever follows the list (which must be
+// after the terminator, if there is one).
+pub fn itemize_list<T, I, F1, F2, F3>(codemap: &CodeMap,
+                                      prefix: Vec<ListItem>,
+                                      it: I,
+                                      separator: &str,
+                                      terminator: &str,
+                                      get_lo: F1,
+                                      get_hi: F2,
+                                      get_item_string: F3,
+                                      mut prev_span_end: BytePos,
+                                      next_span_start: BytePos)
+    -> Vec<ListItem>
+    where I: Iterator<Item=T>,
+          F1: Fn(&T) -> BytePos,
+          F2: Fn(&T) -> BytePos,
+          F3: Fn(&T) -> String
+{
+    let mut result = prefix;
+    let mut it = it.peekable();
+
+    while let Some(item) = it.next() {
+        let lo = get_lo(&item);
+        let hi = get_hi(&item);
+        let last = it.peek().is_none();
+
+        let pre_snippet = snippet(codemap, prev_span_end, lo);
+        let pre_snippet = pre_snippet.trim();
+        let pre_comment = if pre_snippet.len() > 0 {
+            Some(pre_snippet.to_owned())
+        } else {
+            None
+        };
+
+        let next_start = it.peek().map_or(next_span_start, |next| get_lo(next));
+        let post_snippet = snippet(codemap, hi, next_start);
+        let comment_end = if last {
+            // The last item, stop at the end of the list.
+            find_uncommented(&post_snippet, terminator).unwrap_or(post_snippet.len())
+        } else {
+            match find_uncommented(&post_snippet, separator) {
+                Some(sep) => {
+                    let sep_end = sep + separator.len();
+                    sep_end + same_line_comments_len(&post_snippet[sep_end..])
+                }
+                None => post_snippet.len(),
+            }
+        };
+
+        // Remove the separator, but keep comments on either side of it.
+        let post_snippet = &post_snippet[..comment_end];
+        let post_snippet = match find_uncommented(post_snippet, separator) {
+            Some(sep) => {
+                format!("{} {}",
+                        post_snippet[..sep].trim(),
+                        post_snippet[sep + separator.len()..].trim())
+            }
+            None => post_snippet.to_owned(),
+        };
+        let post_snippet = post_snippet.trim();
+        let post_comment = if post_snippet.len() > 0 {
+            Some(post_snippet.to_owned())
+        } else {
+            None
+        };
+
+        result.push(ListItem {
+            pre_comment: pre_comment,
+            item: get_item_string(&item),
+            post_comment: post_comment,
+        });
+        prev_span_end = hi + BytePos(comment_end as u32);
+    }
+
+    result
+}
+
+// The length of any comments at the start of text which begin on the first
+// line.
+fn same_line_comments_len(text: &str) -> usize {
+    let mut pos = 0;
+    loop {
+        let rest = &text[pos..];
+        let rest = rest.trim_left_matches(|c: char| c == ' ' || c == '\t');
+        match comment_len(rest) {
+            Some(len) => pos = text.len() - rest.len() + len,
+            None => return pos,
+        }
+    }
+}
+
+fn snippet(codemap: &CodeMap, lo: BytePos, hi: BytePos) -> String {
+    codemap.span_to_snippet(codemap::mk_sp(lo, hi)).unwrap_or(String::new())
+}
+
 fn needs_trailing_separator(separator_tactic: SeparatorTactic, list_tactic: ListTactic) -> bool {
     match separator_tactic {
         SeparatorTactic::Always => true,
@@ -173,16 +477,6 @@ fn needs_trailing_separator(separator_tactic: SeparatorTactic, list_tactic: List
     }
 }
 
-fn calculate_width(items:&[(String, String)]) -> usize {
-    let missed_width = items.iter().map(|&(_, ref s)| {
-        let text_len = s.trim().len();
-        if text_len > 0 {
-            // We'll put a space before any comment.
-            text_len + 1
-        } else {
-            text_len
-        }
-    }).fold(0, |a, l| a + l);
-    let item_width = items.iter().map(|&(ref s, _)| s.len()).fold(0, |a, l| a + l);
-    missed_width + item_width
+fn calculate_width(items: &[ListItem]) -> usize {
+    items.iter().map(|item| item.width()).fold(0, |a, l| a + l)
 }
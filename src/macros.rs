@@ -0,0 +1,287 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Formatting of function-like macro invocations, e.g., `vec![a, b]` or
+// `println!("{}", x)`. We only know how to format invocations whose arguments
+// parse as a comma-separated list of expressions; these are formatted like
+// function calls, keeping the original delimiters. Anything else (e.g.,
+// `foo!(key => value)`) is left alone.
+//
+// Also formatting of `macro_rules!` definitions.
+
+use visitor::FmtVisitor;
+use rewrite::{Rewrite, RewriteContext};
+use expr::rewrite_call_inner;
+use utils::{make_indent, silent_parse_session};
+
+use syntax::ast;
+use syntax::codemap;
+use syntax::parse::tts_to_parser;
+use syntax::parse::token::{self, Token};
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum MacroStyle {
+    Parens,
+    Brackets,
+    Braces,
+}
+
+impl MacroStyle {
+    fn delims(&self) -> (&'static str, &'static str) {
+        match *self {
+            MacroStyle::Parens => ("(", ")"),
+            MacroStyle::Brackets => ("[", "]"),
+            MacroStyle::Braces => ("{", "}"),
+        }
+    }
+}
+
+// Returns None if the arguments to the macro can't be parsed as a list of
+// expressions, in which case the caller should use the original snippet.
+pub fn rewrite_macro(mac: &ast::Mac,
+                     context: &RewriteContext,
+                     width: usize,
+                     offset: usize)
+    -> Option<String>
+{
+    let ast::Mac_::MacInvocTT(ref path, ref tts, _) = mac.node;
+
+    let mut macro_name = try_opt!(path.rewrite(context, width, offset));
+    macro_name.push('!');
+
+    let style = try_opt!(macro_style(mac, context));
+    let delims = style.delims();
+
+    if tts.len() == 0 {
+        return Some(format!("{}{}{}", macro_name, delims.0, delims.1));
+    }
+
+    // We don't want to report errors if the arguments aren't expressions, so
+    // don't use the session the crate was parsed with.
+    let parse_session = silent_parse_session();
+    let mut parser = tts_to_parser(&parse_session, tts.clone(), Vec::new());
+    let mut args = Vec::new();
+    loop {
+        match parser.parse_expr_nopanic() {
+            Ok(expr) => args.push(expr),
+            Err(..) => return None,
+        }
+
+        match parser.token {
+            Token::Eof => break,
+            Token::Comma => {}
+            _ => return None,
+        }
+
+        let _ = parser.bump();
+
+        // Allow (and drop) a trailing comma.
+        if parser.token == Token::Eof {
+            break;
+        }
+    }
+    // The parser may have recovered from an error.
+    if parse_session.span_diagnostic.handler.err_count() > 0 {
+        return None;
+    }
+
+    rewrite_call_inner(context, &macro_name, &args, delims, mac.span, width, offset)
+}
+
+// The delimiters are not part of the token trees, so we must look for
+// them in the source.
+fn macro_style(mac: &ast::Mac, context: &RewriteContext) -> Option<MacroStyle> {
+    let snippet = context.snippet(mac.span);
+    let bang_pos = try_opt!(snippet.find('!'));
+
+    match snippet[bang_pos + 1..].trim_left().chars().next() {
+        Some('(') => Some(MacroStyle::Parens),
+        Some('[') => Some(MacroStyle::Brackets),
+        Some('{') => Some(MacroStyle::Braces),
+        _ => None,
+    }
+}
+
+impl<'a> FmtVisitor<'a> {
+    // Formats a `macro_rules!` definition, one rule per line:
+    //
+    // macro_rules! foo {
+    //     ($x:expr) => {
+    //         $x + 1
+    //     };
+    // }
+    //
+    // Returns None if mac is not a macro definition or is not a list of
+    // `matcher => transcriber` rules. Transcribers which don't parse as Rust
+    // are left as they are.
+    pub fn rewrite_macro_def(&self, ident: ast::Ident, mac: &ast::Mac) -> Option<String> {
+        let ast::Mac_::MacInvocTT(ref path, ref tts, _) = mac.node;
+        if path.segments.len() != 1 ||
+           &*token::get_ident(path.segments[0].identifier) != "macro_rules" {
+            return None;
+        }
+
+        let mut rules = Vec::new();
+        let mut i = 0;
+        while i < tts.len() {
+            let matcher = match tts[i] {
+                ast::TokenTree::TtDelimited(_, ref delimited) => delimited.clone(),
+                _ => return None,
+            };
+            match tts.get(i + 1) {
+                Some(&ast::TokenTree::TtToken(_, Token::FatArrow)) => {}
+                _ => return None,
+            }
+            let transcriber = match tts.get(i + 2) {
+                Some(&ast::TokenTree::TtDelimited(_, ref delimited)) => delimited.clone(),
+                _ => return None,
+            };
+            rules.push((matcher, transcriber));
+
+            i += 3;
+            match tts.get(i) {
+                Some(&ast::TokenTree::TtToken(_, Token::Semi)) => i += 1,
+                Some(_) => return None,
+                None => {}
+            }
+        }
+
+        let rule_indent = self.block_indent + config!(tab_spaces);
+        let body_indent = rule_indent + config!(tab_spaces);
+
+        let mut result = format!("macro_rules! {} {{\n", &token::get_ident(ident));
+        for (matcher, transcriber) in rules {
+            let matcher_str = self.snippet(codemap::mk_sp(matcher.open_span.hi,
+                                                          matcher.close_span.lo));
+            result.push_str(&make_indent(rule_indent));
+            result.push('(');
+            result.push_str(matcher_str.trim());
+            result.push_str(") => {");
+
+            let body = self.snippet(codemap::mk_sp(transcriber.open_span.hi,
+                                                   transcriber.close_span.lo));
+            match format_transcriber(&body, body_indent) {
+                Some(ref body_str) if body_str.len() == 0 => {}
+                Some(body_str) => {
+                    result.push('\n');
+                    result.push_str(&body_str);
+                    result.push('\n');
+                    result.push_str(&make_indent(rule_indent));
+                }
+                // Leave the transcriber as we found it.
+                None => result.push_str(&body),
+            }
+            result.push_str("};\n");
+        }
+        result.push_str(&make_indent(self.block_indent));
+        result.push('}');
+
+        Some(result)
+    }
+}
+
+// Format the body of a macro rule, indenting every line by indent. Returns
+// None if the body does not parse as items or statements.
+fn format_transcriber(body: &str, indent: usize) -> Option<String> {
+    let body = body.trim();
+    if body.len() == 0 {
+        return Some(String::new());
+    }
+
+    let (source, names) = match replace_metavars(body) {
+        Some(result) => result,
+        None => return None,
+    };
+
+    let mut result = match ::format_code(&source) {
+        Some(formatted) => formatted,
+        None => return None,
+    };
+    for name in &names {
+        result = replace_word(&result, &format!("z{}", name), &format!("${}", name));
+    }
+
+    let indent = make_indent(indent);
+    Some(result.lines()
+               .map(|l| if l.trim().len() == 0 {
+                   String::new()
+               } else {
+                   format!("{}{}", indent, l)
+               })
+               .collect::<Vec<_>>()
+               .connect("\n"))
+}
+
+// Replace each metavariable `$name` with `zname` (which has the same width)
+// so that a transcriber can be parsed as ordinary Rust. Returns the new text
+// and the names that were replaced, or None if there are repetitions (which we
+// can't handle) or if the replacement would clash with an existing identifier.
+fn replace_metavars(text: &str) -> Option<(String, Vec<String>)> {
+    let mut result = String::with_capacity(text.len());
+    let mut names: Vec<String> = Vec::new();
+
+    let mut rest = text;
+    while let Some(i) = rest.find('$') {
+        result.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+
+        let name_len = rest.find(|c: char| !is_ident_char(c)).unwrap_or(rest.len());
+        if name_len == 0 {
+            // `$(...)` or a stray `$`.
+            return None;
+        }
+        let name = &rest[..name_len];
+        if contains_word(text, &format!("z{}", name)) {
+            return None;
+        }
+
+        result.push('z');
+        result.push_str(name);
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_owned());
+        }
+        rest = &rest[name_len..];
+    }
+    result.push_str(rest);
+
+    Some((result, names))
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Does text contain word, not as part of a larger identifier?
+fn contains_word(text: &str, word: &str) -> bool {
+    replace_word(text, word, "") != text
+}
+
+// Replace occurrences of from which are not part of a larger identifier.
+fn replace_word(text: &str, from: &str, to: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut pos = 0;
+    while let Some(i) = text[pos..].find(from) {
+        let start = pos + i;
+        let end = start + from.len();
+        let before_ok = text[..start].chars().rev().next().map_or(true, |c| !is_ident_char(c));
+        let after_ok = text[end..].chars().next().map_or(true, |c| !is_ident_char(c));
+
+        result.push_str(&text[pos..start]);
+        if before_ok && after_ok {
+            result.push_str(to);
+        } else {
+            result.push_str(from);
+        }
+        pos = end;
+    }
+    result.push_str(&text[pos..]);
+
+    result
+}
@@ -10,6 +10,7 @@
 
 use utils::make_indent;
 use visitor::FmtVisitor;
+use comment::rewrite_comments_in_whitespace;
 
 use syntax::codemap::{self, BytePos};
 
@@ -17,25 +18,28 @@ impl<'a> FmtVisitor<'a> {
     // TODO these format_missing methods are ugly. Refactor and add unit tests
     // for the central whitespace stripping loop.
     pub fn format_missing(&mut self, end: BytePos) {
-        self.format_missing_inner(end, |this, last_snippet, file_name, _| {
+        let comment_indent = self.block_indent;
+        self.format_missing_inner(end, comment_indent, |this, last_snippet, file_name, _| {
             this.changes.push_str(file_name, last_snippet)
         })
     }
 
     pub fn format_missing_with_indent(&mut self, end: BytePos) {
-        self.format_missing_inner(end, |this, last_snippet, file_name, snippet| {
-            this.changes.push_str(file_name, last_snippet.trim_right());
-            if last_snippet == snippet {
-                // No new lines in the snippet.
-                this.changes.push_str(file_name, "\n");
-            }
-            let indent = make_indent(this.block_indent);
-            this.changes.push_str(file_name, &indent);
-        })
+        let comment_indent = self.block_indent;
+        self.format_missing_inner(end, comment_indent, indent_last_snippet)
+    }
+
+    // For the span before the closing brace of a block. block_indent should be
+    // the indent of the brace; any comments belong to the block and are
+    // indented one level further.
+    pub fn format_missing_block_end(&mut self, end: BytePos) {
+        let comment_indent = self.block_indent + config!(tab_spaces);
+        self.format_missing_inner(end, comment_indent, indent_last_snippet)
     }
 
     fn format_missing_inner<F: Fn(&mut FmtVisitor, &str, &str, &str)>(&mut self,
                                                                       end: BytePos,
+                                                                      comment_indent: usize,
                                                                       process_last_snippet: F)
     {
         let start = self.last_pos;
@@ -60,6 +64,10 @@ impl<'a> FmtVisitor<'a> {
             let span = codemap::mk_sp(BytePos(start), BytePos(end));
             let file_name = &self.codemap.span_to_filename(span);
             let snippet = self.snippet(span);
+            // Gaps which contain only comments can have their comments
+            // rewritten, anything else we must copy.
+            let snippet = rewrite_comments_in_whitespace(&snippet, comment_indent)
+                              .unwrap_or(snippet);
 
             self.write_snippet(&snippet,
                                file_name,
@@ -106,3 +114,13 @@ impl<'a> FmtVisitor<'a> {
         }
     }
 }
+
+fn indent_last_snippet(this: &mut FmtVisitor, last_snippet: &str, file_name: &str, snippet: &str) {
+    this.changes.push_str(file_name, last_snippet.trim_right());
+    if last_snippet == snippet {
+        // No new lines in the snippet.
+        this.changes.push_str(file_name, "\n");
+    }
+    let indent = make_indent(this.block_indent);
+    this.changes.push_str(file_name, &indent);
+}
@@ -0,0 +1,52 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rewrite::{Rewrite, RewriteContext};
+
+use syntax::ast;
+use syntax::parse::token;
+use syntax::print::pprust;
+
+impl Rewrite for ast::Pat {
+    fn rewrite(&self, context: &RewriteContext, width: usize, offset: usize) -> Option<String> {
+        match self.node {
+            ast::Pat_::PatWild(ast::PatWildKind::PatWildSingle) => Some("_".to_owned()),
+            ast::Pat_::PatIdent(binding_mode, ref ident, ref sub_pat) => {
+                let prefix = match binding_mode {
+                    ast::BindingMode::BindByRef(ast::Mutability::MutMutable) => "ref mut ",
+                    ast::BindingMode::BindByRef(ast::Mutability::MutImmutable) => "ref ",
+                    ast::BindingMode::BindByValue(ast::Mutability::MutMutable) => "mut ",
+                    ast::BindingMode::BindByValue(ast::Mutability::MutImmutable) => "",
+                };
+                let mut result = format!("{}{}", prefix, token::get_ident(ident.node));
+                if let Some(ref sub_pat) = *sub_pat {
+                    // 3 = ` @ `
+                    let overhead = result.len() + 3;
+                    let budget = try_opt!(width.checked_sub(overhead));
+                    let sub_pat_str = try_opt!(sub_pat.rewrite(context, budget, offset + overhead));
+                    result.push_str(" @ ");
+                    result.push_str(&sub_pat_str);
+                }
+                if result.len() > width {
+                    return None;
+                }
+                Some(result)
+            }
+            // TODO other patterns.
+            _ => {
+                let result = pprust::pat_to_string(self);
+                if result.len() > width {
+                    return None;
+                }
+                Some(result)
+            }
+        }
+    }
+}
@@ -0,0 +1,42 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// A generic trait to abstract the rewriting of an element (of the AST).
+
+use syntax::codemap::{CodeMap, Span};
+
+pub trait Rewrite {
+    // Rewrite self into a string which starts at column offset and whose first
+    // line fits in width columns. Later lines are indented by the rewrite
+    // itself and should fit in max_width. Returns None if self can't be
+    // rewritten within these limits, so the caller may try another layout.
+    fn rewrite(&self, context: &RewriteContext, width: usize, offset: usize) -> Option<String>;
+}
+
+// What a rewrite needs to know about the source and the surrounding code.
+pub struct RewriteContext<'a> {
+    pub codemap: &'a CodeMap,
+    // The indentation of the block (or item) containing the element.
+    pub block_indent: usize,
+}
+
+impl<'a> RewriteContext<'a> {
+    pub fn snippet(&self, span: Span) -> String {
+        match self.codemap.span_to_snippet(span) {
+            Ok(s) => s,
+            Err(_) => {
+                println!("Couldn't make snippet for span {:?}->{:?}",
+                         self.codemap.lookup_char_pos(span.lo),
+                         self.codemap.lookup_char_pos(span.hi));
+                "".to_owned()
+            }
+        }
+    }
+}
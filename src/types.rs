@@ -8,32 +8,38 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use visitor::FmtVisitor;
+use rewrite::{Rewrite, RewriteContext};
+use utils::{make_indent, last_line_offset, fits_in};
+use lists::{write_list, ListItem, ListFormatting, SeparatorTactic, ListTactic};
+use items::rewrite_return;
 
-use syntax::ast;
+use syntax::{ast, abi, ptr};
 use syntax::parse::token;
 use syntax::print::pprust;
 
-impl<'a> FmtVisitor<'a> {
-    pub fn rewrite_pred(&self, predicate: &ast::WherePredicate) -> String
-    {
+impl Rewrite for ast::WherePredicate {
+    fn rewrite(&self, context: &RewriteContext, width: usize, offset: usize) -> Option<String> {
         // TODO dead spans
-        // TODO assumes we'll always fit on one line...
-        match predicate {
+        let result = match self {
             &ast::WherePredicate::BoundPredicate(ast::WhereBoundPredicate{ref bound_lifetimes,
                                                                           ref bounded_ty,
                                                                           ref bounds,
                                                                           ..}) => {
+                let type_str = try_opt!(bounded_ty.rewrite(context, width, offset));
+                let bound_strs = try_opt!(bounds.iter()
+                                                .map(|b| b.rewrite(context, width, offset))
+                                                .collect::<Option<Vec<_>>>());
+                let bounds_str = bound_strs.connect(" + ");
                 if bound_lifetimes.len() > 0 {
-                    format!("for<{}> {}: {}",
-                            bound_lifetimes.iter().map(|l| self.rewrite_lifetime_def(l)).collect::<Vec<_>>().connect(", "),
-                            pprust::ty_to_string(bounded_ty),
-                            bounds.iter().map(|b| self.rewrite_ty_bound(b)).collect::<Vec<_>>().connect(" + "))
+                    let lifetime_strs = try_opt!(bound_lifetimes.iter()
+                                                                .map(|l| l.rewrite(context,
+                                                                                   width,
+                                                                                   offset))
+                                                                .collect::<Option<Vec<_>>>());
+                    format!("for<{}> {}: {}", lifetime_strs.connect(", "), type_str, bounds_str)
 
                 } else {
-                    format!("{}: {}",
-                            pprust::ty_to_string(bounded_ty),
-                            bounds.iter().map(|b| self.rewrite_ty_bound(b)).collect::<Vec<_>>().connect(" + "))
+                    format!("{}: {}", type_str, bounds_str)
                 }
             }
             &ast::WherePredicate::RegionPredicate(ast::WhereRegionPredicate{ref lifetime,
@@ -41,65 +47,485 @@ impl<'a> FmtVisitor<'a> {
                                                                             ..}) => {
                 format!("{}: {}",
                         pprust::lifetime_to_string(lifetime),
-                        bounds.iter().map(|l| pprust::lifetime_to_string(l)).collect::<Vec<_>>().connect(" + "))
+                        bounds.iter()
+                              .map(|l| pprust::lifetime_to_string(l))
+                              .collect::<Vec<_>>()
+                              .connect(" + "))
             }
             &ast::WherePredicate::EqPredicate(ast::WhereEqPredicate{ref path, ref ty, ..}) => {
-                format!("{} = {}", pprust::path_to_string(path), pprust::ty_to_string(ty))
+                let path_str = try_opt!(path.rewrite(context, width, offset));
+                // 3 = ` = `
+                let ty_offset = last_line_offset(&path_str, offset) + 3;
+                let budget = try_opt!((offset + width).checked_sub(ty_offset));
+                let ty_str = try_opt!(ty.rewrite(context, budget, ty_offset));
+                format!("{} = {}", path_str, ty_str)
             }
+        };
+
+        if fits_in(&result, width) {
+            Some(result)
+        } else {
+            None
         }
     }
+}
 
-    pub fn rewrite_lifetime_def(&self, lifetime: &ast::LifetimeDef) -> String
-    {
-        if lifetime.bounds.len() == 0 {
-            return pprust::lifetime_to_string(&lifetime.lifetime);
+impl Rewrite for ast::LifetimeDef {
+    fn rewrite(&self, _: &RewriteContext, _: usize, _: usize) -> Option<String> {
+        if self.bounds.len() == 0 {
+            return Some(pprust::lifetime_to_string(&self.lifetime));
         }
 
-        format!("{}: {}",
-                pprust::lifetime_to_string(&lifetime.lifetime),
-                lifetime.bounds.iter().map(|l| pprust::lifetime_to_string(l)).collect::<Vec<_>>().connect(" + "))
+        Some(format!("{}: {}",
+                     pprust::lifetime_to_string(&self.lifetime),
+                     self.bounds.iter()
+                                .map(|l| pprust::lifetime_to_string(l))
+                                .collect::<Vec<_>>()
+                                .connect(" + ")))
     }
+}
 
-    pub fn rewrite_ty_bound(&self, bound: &ast::TyParamBound) -> String
-    {
-        match *bound {
+impl Rewrite for ast::TyParamBound {
+    fn rewrite(&self, context: &RewriteContext, width: usize, offset: usize) -> Option<String> {
+        match *self {
             ast::TyParamBound::TraitTyParamBound(ref tref, ast::TraitBoundModifier::None) => {
-                self.rewrite_poly_trait_ref(tref)
+                tref.rewrite(context, width, offset)
             }
             ast::TyParamBound::TraitTyParamBound(ref tref, ast::TraitBoundModifier::Maybe) => {
-                format!("?{}", self.rewrite_poly_trait_ref(tref))
+                let budget = try_opt!(width.checked_sub(1));
+                Some(format!("?{}", try_opt!(tref.rewrite(context, budget, offset + 1))))
             }
             ast::TyParamBound::RegionTyParamBound(ref l) => {
-                pprust::lifetime_to_string(l)
+                Some(pprust::lifetime_to_string(l))
             }
         }
     }
+}
 
-    pub fn rewrite_ty_param(&self, ty_param: &ast::TyParam) -> String
-    {
+impl Rewrite for ast::TyParam {
+    fn rewrite(&self, context: &RewriteContext, width: usize, offset: usize) -> Option<String> {
         let mut result = String::with_capacity(128);
-        result.push_str(&token::get_ident(ty_param.ident));
-        if ty_param.bounds.len() > 0 {
+        result.push_str(&token::get_ident(self.ident));
+        if self.bounds.len() > 0 {
             result.push_str(": ");
-            result.push_str(&ty_param.bounds.iter().map(|b| self.rewrite_ty_bound(b)).collect::<Vec<_>>().connect(" + "));
+            let bound_strs = try_opt!(self.bounds
+                                          .iter()
+                                          .map(|b| b.rewrite(context, width, offset))
+                                          .collect::<Option<Vec<_>>>());
+            result.push_str(&bound_strs.connect(" + "));
         }
-        if let Some(ref def) = ty_param.default {
+        if let Some(ref def) = self.default {
             result.push_str(" = ");
-            result.push_str(&pprust::ty_to_string(&def));
+            let def_offset = last_line_offset(&result, offset);
+            let budget = try_opt!((offset + width).checked_sub(def_offset));
+            let def_str = try_opt!(def.rewrite(context, budget, def_offset));
+            result.push_str(&def_str);
+        }
+
+        Some(result)
+    }
+}
+
+impl Rewrite for ast::PolyTraitRef {
+    fn rewrite(&self, context: &RewriteContext, width: usize, offset: usize) -> Option<String> {
+        if self.bound_lifetimes.len() > 0 {
+            let lifetime_strs = try_opt!(self.bound_lifetimes
+                                             .iter()
+                                             .map(|l| l.rewrite(context, width, offset))
+                                             .collect::<Option<Vec<_>>>());
+            let lifetime_str = lifetime_strs.connect(", ");
+            // 6 = `for<> `
+            let extra_offset = lifetime_str.len() + 6;
+            let budget = try_opt!(width.checked_sub(extra_offset));
+            let path_str = try_opt!(self.trait_ref.path.rewrite(context,
+                                                                budget,
+                                                                offset + extra_offset));
+            Some(format!("for<{}> {}", lifetime_str, path_str))
+        } else {
+            self.trait_ref.path.rewrite(context, width, offset)
+        }
+    }
+}
+
+impl Rewrite for ast::Ty {
+    fn rewrite(&self, context: &RewriteContext, width: usize, offset: usize) -> Option<String> {
+        match self.node {
+            ast::Ty_::TyVec(ref ty) => {
+                // 2 = `[` and `]`
+                let budget = try_opt!(width.checked_sub(2));
+                Some(format!("[{}]", try_opt!(ty.rewrite(context, budget, offset + 1))))
+            }
+            ast::Ty_::TyFixedLengthVec(ref ty, ref len) => {
+                let len_str = context.snippet(len.span);
+                // 4 = `[`, `; ` and `]`
+                let budget = try_opt!(width.checked_sub(len_str.len() + 4));
+                let ty_str = try_opt!(ty.rewrite(context, budget, offset + 1));
+                Some(format!("[{}; {}]", ty_str, len_str))
+            }
+            ast::Ty_::TyPtr(ref mt) => {
+                let prefix = match mt.mutbl {
+                    ast::Mutability::MutMutable => "*mut ",
+                    ast::Mutability::MutImmutable => "*const ",
+                };
+                let budget = try_opt!(width.checked_sub(prefix.len()));
+                Some(format!("{}{}",
+                             prefix,
+                             try_opt!(mt.ty.rewrite(context, budget, offset + prefix.len()))))
+            }
+            ast::Ty_::TyRptr(ref lifetime, ref mt) => {
+                let mut prefix = "&".to_owned();
+                if let Some(ref l) = *lifetime {
+                    prefix.push_str(&pprust::lifetime_to_string(l));
+                    prefix.push(' ');
+                }
+                if mt.mutbl == ast::Mutability::MutMutable {
+                    prefix.push_str("mut ");
+                }
+                let budget = try_opt!(width.checked_sub(prefix.len()));
+                let ty_str = try_opt!(mt.ty.rewrite(context, budget, offset + prefix.len()));
+                Some(prefix + &ty_str)
+            }
+            ast::Ty_::TyParen(ref ty) => {
+                // 2 = `(` and `)`
+                let budget = try_opt!(width.checked_sub(2));
+                Some(format!("({})", try_opt!(ty.rewrite(context, budget, offset + 1))))
+            }
+            ast::Ty_::TyTup(ref tys) => {
+                rewrite_ty_tuple(context, tys, width, offset)
+            }
+            ast::Ty_::TyBareFn(ref bare_fn) => {
+                rewrite_bare_fn(context, bare_fn, width, offset)
+            }
+            ast::Ty_::TyPath(ref qself, ref path) => {
+                rewrite_path(context, qself.as_ref(), path, false, width, offset)
+            }
+            ast::Ty_::TyObjectSum(ref ty, ref bounds) => {
+                let ty_str = try_opt!(ty.rewrite(context, width, offset));
+                let bound_strs = try_opt!(bounds.iter()
+                                                .map(|b| b.rewrite(context, width, offset))
+                                                .collect::<Option<Vec<_>>>());
+                Some(rewrite_bound_list(Some(ty_str).into_iter().chain(bound_strs).collect(),
+                                        width,
+                                        offset))
+            }
+            ast::Ty_::TyPolyTraitRef(ref bounds) => {
+                let bound_strs = try_opt!(bounds.iter()
+                                                .map(|b| b.rewrite(context, width, offset))
+                                                .collect::<Option<Vec<_>>>());
+                Some(rewrite_bound_list(bound_strs, width, offset))
+            }
+            ast::Ty_::TyInfer => Some("_".to_owned()),
+            // TODO typeof and macros in type position.
+            _ => {
+                let result = pprust::ty_to_string(self);
+                if result.len() > width {
+                    return None;
+                }
+                Some(result)
+            }
         }
+    }
+}
 
-        result
+impl Rewrite for ast::Path {
+    fn rewrite(&self, context: &RewriteContext, width: usize, offset: usize) -> Option<String> {
+        rewrite_path(context, None, self, false, width, offset)
     }
+}
 
-    fn rewrite_poly_trait_ref(&self, t: &ast::PolyTraitRef) -> String
-    {
-        if t.bound_lifetimes.len() > 0 {
-            format!("for<{}> {}",
-                    t.bound_lifetimes.iter().map(|l| self.rewrite_lifetime_def(l)).collect::<Vec<_>>().connect(", "),
-                    pprust::path_to_string(&t.trait_ref.path))
+fn rewrite_ty_tuple(context: &RewriteContext,
+                    tys: &[ptr::P<ast::Ty>],
+                    width: usize,
+                    offset: usize)
+    -> Option<String>
+{
+    // opening paren
+    let indent = offset + 1;
+    // In case of length 1, need a trailing comma
+    if tys.len() == 1 {
+        let budget = try_opt!(width.checked_sub(3));
+        return Some(format!("({},)", try_opt!(tys[0].rewrite(context, budget, indent))));
+    }
+
+    let budget = try_opt!(width.checked_sub(2));
+    let ty_strs = try_opt!(tys.iter()
+                              .map(|ty| ty.rewrite(context, budget, indent))
+                              .collect::<Option<Vec<_>>>());
+    Some(format!("({})", write_ty_list(ty_strs, budget, indent)))
+}
+
+fn rewrite_bare_fn(context: &RewriteContext,
+                   bare_fn: &ast::BareFnTy,
+                   width: usize,
+                   offset: usize)
+    -> Option<String>
+{
+    let mut result = String::with_capacity(64);
+    if bare_fn.lifetimes.len() > 0 {
+        let lifetime_strs = try_opt!(bare_fn.lifetimes
+                                            .iter()
+                                            .map(|l| l.rewrite(context, width, offset))
+                                            .collect::<Option<Vec<_>>>());
+        result.push_str("for<");
+        result.push_str(&lifetime_strs.connect(", "));
+        result.push_str("> ");
+    }
+    if let ast::Unsafety::Unsafe = bare_fn.unsafety {
+        result.push_str("unsafe ");
+    }
+    if bare_fn.abi != abi::Rust {
+        result.push_str("extern ");
+        result.push_str(&bare_fn.abi.to_string());
+        result.push(' ');
+    }
+    result.push_str("fn(");
+
+    let decl = &bare_fn.decl;
+    let ret_str = try_opt!(rewrite_return(context,
+                                          &decl.output,
+                                          try_opt!(width.checked_sub(result.len())),
+                                          offset + result.len()));
+
+    // 1 = `)`
+    let arg_indent = offset + result.len();
+    let budget = try_opt!(width.checked_sub(result.len() + 1));
+    let mut arg_strs = try_opt!(decl.inputs
+                                    .iter()
+                                    .map(|a| rewrite_fn_type_input(context, a, budget, arg_indent))
+                                    .collect::<Option<Vec<_>>>());
+    if decl.variadic {
+        arg_strs.push("...".to_owned());
+    }
+    result.push_str(&write_ty_list(arg_strs, budget, arg_indent));
+    result.push(')');
 
+    if ret_str.len() > 0 {
+        // 1 = space before the return type
+        if result.contains('\n') || result.len() + ret_str.len() + 1 > width {
+            result.push('\n');
+            result.push_str(&make_indent(arg_indent));
         } else {
-            pprust::path_to_string(&t.trait_ref.path)
+            result.push(' ');
         }
+        result.push_str(&ret_str);
     }
+
+    Some(result)
+}
+
+// Arguments in a fn type may or may not be named.
+fn rewrite_fn_type_input(context: &RewriteContext,
+                         arg: &ast::Arg,
+                         width: usize,
+                         offset: usize)
+    -> Option<String>
+{
+    if let ast::Pat_::PatIdent(_, ref ident, _) = arg.pat.node {
+        if ident.node.name == token::special_idents::invalid.name {
+            return arg.ty.rewrite(context, width, offset);
+        }
+    }
+
+    let pat_str = try_opt!(arg.pat.rewrite(context, width, offset));
+    // 2 = `: `
+    let overhead = pat_str.len() + 2;
+    let budget = try_opt!(width.checked_sub(overhead));
+    Some(format!("{}: {}", pat_str, try_opt!(arg.ty.rewrite(context, budget, offset + overhead))))
+}
+
+// A path, possibly qualified, e.g., `<T as Trait>::Assoc`. In expression
+// context generic arguments need a leading `::`, e.g., `Vec::<T>::new`.
+pub fn rewrite_path(context: &RewriteContext,
+                    qself: Option<&ast::QSelf>,
+                    path: &ast::Path,
+                    expr_context: bool,
+                    width: usize,
+                    offset: usize)
+    -> Option<String>
+{
+    let global = if path.global {
+        "::"
+    } else {
+        ""
+    };
+    let mut segments = &path.segments[..];
+
+    let result = match qself {
+        Some(qself) => {
+            let mut result = "<".to_owned();
+            let budget = try_opt!(width.checked_sub(1));
+            result.push_str(&try_opt!(qself.ty.rewrite(context, budget, offset + 1)));
+            if qself.position > 0 {
+                result.push_str(" as ");
+                result.push_str(global);
+                result = try_opt!(rewrite_path_segments(context,
+                                                        result,
+                                                        &segments[..qself.position],
+                                                        expr_context,
+                                                        width,
+                                                        offset));
+            }
+            result.push_str(">::");
+            segments = &segments[qself.position..];
+            result
+        }
+        None => global.to_owned(),
+    };
+
+    rewrite_path_segments(context, result, segments, expr_context, width, offset)
+}
+
+// Appends segments to a partially written path.
+fn rewrite_path_segments(context: &RewriteContext,
+                         mut result: String,
+                         segments: &[ast::PathSegment],
+                         expr_context: bool,
+                         width: usize,
+                         offset: usize)
+    -> Option<String>
+{
+    for (i, segment) in segments.iter().enumerate() {
+        if i > 0 {
+            result.push_str("::");
+        }
+
+        let segment_offset = last_line_offset(&result, offset);
+        let used_width = segment_offset.saturating_sub(offset);
+        let segment_str = try_opt!(rewrite_segment(context,
+                                                   segment,
+                                                   expr_context,
+                                                   try_opt!(width.checked_sub(used_width)),
+                                                   segment_offset));
+        result.push_str(&segment_str);
+    }
+
+    Some(result)
+}
+
+fn rewrite_segment(context: &RewriteContext,
+                   segment: &ast::PathSegment,
+                   expr_context: bool,
+                   width: usize,
+                   offset: usize)
+    -> Option<String>
+{
+    let mut result = token::get_ident(segment.identifier).to_string();
+
+    match segment.parameters {
+        ast::PathParameters::AngleBracketedParameters(ref data) => {
+            let has_params = data.lifetimes.len() + data.types.len() + data.bindings.len() > 0;
+            if expr_context && has_params {
+                result.push_str("::");
+            }
+            let budget = try_opt!(width.checked_sub(result.len()));
+            let params_str = try_opt!(rewrite_angle_params(context,
+                                                           data,
+                                                           budget,
+                                                           offset + result.len()));
+            result.push_str(&params_str);
+        }
+        ast::PathParameters::ParenthesizedParameters(ref data) => {
+            let budget = try_opt!(width.checked_sub(result.len()));
+            let params_str = try_opt!(rewrite_paren_params(context,
+                                                           data,
+                                                           budget,
+                                                           offset + result.len()));
+            result.push_str(&params_str);
+        }
+    }
+
+    Some(result)
+}
+
+// E.g., the `(A, B) -> C` in `Fn(A, B) -> C`.
+fn rewrite_paren_params(context: &RewriteContext,
+                        data: &ast::ParenthesizedParameterData,
+                        width: usize,
+                        offset: usize)
+    -> Option<String>
+{
+    // 4 = ` -> `
+    let output_str = match data.output {
+        Some(ref ty) => {
+            let budget = try_opt!(width.checked_sub(4));
+            format!(" -> {}", try_opt!(ty.rewrite(context, budget, offset + 4)))
+        }
+        None => String::new(),
+    };
+
+    // 2 = `(` and `)`
+    let budget = try_opt!(width.checked_sub(output_str.len() + 2));
+    let input_strs = try_opt!(data.inputs
+                                  .iter()
+                                  .map(|ty| ty.rewrite(context, budget, offset + 1))
+                                  .collect::<Option<Vec<_>>>());
+    Some(format!("({}){}", write_ty_list(input_strs, budget, offset + 1), output_str))
+}
+
+fn rewrite_angle_params(context: &RewriteContext,
+                        data: &ast::AngleBracketedParameterData,
+                        width: usize,
+                        offset: usize)
+    -> Option<String>
+{
+    if data.lifetimes.len() + data.types.len() + data.bindings.len() == 0 {
+        return Some(String::new());
+    }
+
+    // 2 = `<` and `>`
+    let budget = try_opt!(width.checked_sub(2));
+    let indent = offset + 1;
+
+    let lt_strs = data.lifetimes.iter().map(|l| Some(pprust::lifetime_to_string(l)));
+    let ty_strs = data.types.iter().map(|ty| ty.rewrite(context, budget, indent));
+    let binding_strs = data.bindings.iter().map(|binding| {
+        let name = token::get_ident(binding.ident);
+        // 3 = ` = `
+        let overhead = name.len() + 3;
+        let ty_str = try_opt!(binding.ty.rewrite(context,
+                                                 try_opt!(budget.checked_sub(overhead)),
+                                                 indent + overhead));
+        Some(format!("{} = {}", name, ty_str))
+    });
+
+    let param_strs = try_opt!(lt_strs.chain(ty_strs)
+                                     .chain(binding_strs)
+                                     .collect::<Option<Vec<_>>>());
+    Some(format!("<{}>", write_ty_list(param_strs, budget, indent)))
+}
+
+// Bounds in type position, e.g., `Foo + Send + 'static`.
+pub fn rewrite_bound_list(bound_strs: Vec<String>, width: usize, offset: usize) -> String
+{
+    let items: Vec<_> = bound_strs.into_iter().map(ListItem::from_str).collect();
+    let fmt = ListFormatting {
+        tactic: ListTactic::HorizontalVertical,
+        separator: " +",
+        trailing_separator: SeparatorTactic::Never,
+        indent: offset,
+        h_width: width,
+        v_width: width,
+        align_after: None,
+        align_comments: config!(trailing_comment_align),
+    };
+
+    write_list(&items, &fmt)
+}
+
+// Comma separated list of types or generic parameters.
+fn write_ty_list(item_strs: Vec<String>, width: usize, offset: usize) -> String
+{
+    let items: Vec<_> = item_strs.into_iter().map(ListItem::from_str).collect();
+    let fmt = ListFormatting {
+        tactic: ListTactic::HorizontalVertical,
+        separator: ",",
+        trailing_separator: SeparatorTactic::Never,
+        indent: offset,
+        h_width: width,
+        v_width: width,
+        align_after: None,
+        align_comments: config!(trailing_comment_align),
+    };
+
+    write_list(&items, &fmt)
 }
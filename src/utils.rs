@@ -8,6 +8,20 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use syntax::codemap::{CodeMap, Span, BytePos};
+use syntax::diagnostic::{Emitter, Handler, Level, RenderSpan, SpanHandler};
+use syntax::parse::ParseSess;
+
+use comment::find_uncommented;
+
+// Like try!, but for Option: returns None from the enclosing function if expr
+// is None.
+macro_rules! try_opt {
+    ($expr:expr) => (match $expr {
+        Some(val) => val,
+        None => { return None; }
+    })
+}
 
 #[inline]
 pub fn prev_char(s: &str, mut i: usize) -> usize {
@@ -38,3 +52,71 @@ pub fn make_indent(width: usize) -> String {
     }
     indent
 }
+
+// The column at which the text following `text` will start, assuming `text`
+// itself starts at column `offset`.
+#[inline]
+pub fn last_line_offset(text: &str, offset: usize) -> usize {
+    match text.rfind('\n') {
+        // 1 = the newline itself
+        Some(i) => text.len() - i - 1,
+        None => offset + text.len(),
+    }
+}
+
+#[inline]
+pub fn first_line_width(text: &str) -> usize {
+    match text.find('\n') {
+        Some(i) => i,
+        None => text.len(),
+    }
+}
+
+// Does text fit, if its first line has width columns available? Later lines
+// include their indentation and must fit in max_width.
+pub fn fits_in(text: &str, width: usize) -> bool {
+    text.lines().enumerate().all(|(i, line)| {
+        let available = if i == 0 {
+            width
+        } else {
+            config!(max_width)
+        };
+        line.len() <= available
+    })
+}
+
+// Does source (probably) start with an item, rather than a statement or an
+// expression? Items may start with attributes or doc comments.
+pub fn looks_like_items(source: &str) -> bool {
+    let item_starts = ["#[", "#![", "///", "//!", "/**", "/*!", "pub ", "fn ", "unsafe fn ",
+                       "unsafe impl ", "unsafe impl<", "unsafe trait ", "impl ", "impl<",
+                       "struct ", "enum ", "trait ", "mod ", "use ", "const ", "static ",
+                       "type ", "extern "];
+    item_starts.iter().any(|s| source.starts_with(s))
+}
+
+// A parse session which doesn't report errors, for when we parse some code to
+// see if we can format it and fall back to the original text if we can't.
+// Errors are still counted by the session's handler.
+pub fn silent_parse_session() -> ParseSess {
+    let handler = Handler::with_emitter(false, Box::new(SilentEmitter));
+    ParseSess::with_span_handler(SpanHandler::new(handler, CodeMap::new()))
+}
+
+struct SilentEmitter;
+
+impl Emitter for SilentEmitter {
+    fn emit(&mut self, _: Option<(&CodeMap, Span)>, _: &str, _: Option<&str>, _: Level) {}
+
+    fn custom_emit(&mut self, _: &CodeMap, _: RenderSpan, _: &str, _: Level) {}
+}
+
+// The position just after the first uncommented occurrence of needle in
+// original, e.g., to find the position after the opening paren of a call.
+#[inline]
+pub fn span_after(original: Span, needle: &str, codemap: &CodeMap) -> BytePos {
+    let snippet = codemap.span_to_snippet(original).unwrap();
+    let offset = find_uncommented(&snippet, needle).unwrap() + needle.len();
+
+    original.lo + BytePos(offset as u32)
+}
@@ -12,9 +12,15 @@ use syntax::ast;
 use syntax::codemap::{self, CodeMap, Span, BytePos};
 use syntax::visit;
 
+use std::collections::HashSet;
+
 use utils;
+use comment::{rewrite_doc_comment, is_doc_comment};
+use imports::rewrite_mod_decl;
+use macros::rewrite_macro;
+use rewrite::{Rewrite, RewriteContext};
 
-use SKIP_ANNOTATION;
+use {SKIP_ANNOTATION, DocCommentStyle};
 use changes::ChangeSet;
 
 pub struct FmtVisitor<'a> {
@@ -23,6 +29,21 @@ pub struct FmtVisitor<'a> {
     pub last_pos: BytePos,
     // TODO RAII util for indenting
     pub block_indent: usize,
+    // Names of crates declared with `extern crate`, used for grouping imports.
+    pub extern_crates: Vec<String>,
+    // Names which are used as modules in paths in the current module, used for
+    // ordering the names in use lists.
+    pub module_names: HashSet<String>,
+    // Where glob imports are, as (file name, line), if report_glob_imports is
+    // set.
+    pub glob_imports: Vec<(String, usize)>,
+    // The spans of imports which name resolution found to be unused, if
+    // remove_unused_imports is set.
+    pub unused_imports: Vec<Span>,
+    // The width to pad the left-hand side of const and static items, or of
+    // let statements, to, so that the `=`s of consecutive items or statements
+    // line up (0 for no padding).
+    pub assign_width: usize,
 }
 
 impl<'a, 'v> visit::Visitor<'v> for FmtVisitor<'a> {
@@ -32,9 +53,14 @@ impl<'a, 'v> visit::Visitor<'v> for FmtVisitor<'a> {
                self.codemap.lookup_char_pos(ex.span.hi));
         self.format_missing(ex.span.lo);
         let offset = self.changes.cur_offset_span(ex.span);
-        let new_str = self.rewrite_expr(ex, config!(max_width) - offset, offset);
-        self.changes.push_str_span(ex.span, &new_str);
-        self.last_pos = ex.span.hi;
+        let rewrite = ex.rewrite(&self.get_context(),
+                                 config!(max_width).saturating_sub(offset),
+                                 offset);
+        // If we can't format the expression, leave it for format_missing.
+        if let Some(new_str) = rewrite {
+            self.changes.push_str_span(ex.span, &new_str);
+            self.last_pos = ex.span.hi;
+        }
     }
 
     fn visit_stmt(&mut self, stmt: &'v ast::Stmt) {
@@ -54,6 +80,29 @@ impl<'a, 'v> visit::Visitor<'v> for FmtVisitor<'a> {
         if !skip_missing {
             self.format_missing_with_indent(stmt.span.lo);
         }
+
+        if let ast::Stmt_::StmtMac(ref mac, _) = stmt.node {
+            let offset = self.block_indent;
+            // 1 = trailing semicolon
+            let width = config!(max_width).saturating_sub(offset + 1);
+            // If we can't format the macro, leave it for format_missing.
+            if let Some(new_str) = rewrite_macro(mac, &self.get_context(), width, offset) {
+                self.changes.push_str_span(stmt.span, &new_str);
+                self.last_pos = mac.span.hi;
+            }
+            return;
+        }
+
+        if self.assign_width > 0 {
+            if let Some((lhs, init)) = self.let_lhs(stmt) {
+                let padding = utils::make_indent(self.assign_width - lhs.len());
+                self.changes.push_str_span(stmt.span, &format!("{}{} = ", lhs, padding));
+                self.last_pos = init.span.lo;
+                self.visit_expr(init);
+                return;
+            }
+        }
+
         visit::walk_stmt(self, stmt);
     }
 
@@ -67,9 +116,20 @@ impl<'a, 'v> visit::Visitor<'v> for FmtVisitor<'a> {
         self.last_pos = self.last_pos + BytePos(1);
         self.block_indent += config!(tab_spaces);
 
-        for stmt in &b.stmts {
+        let outer_assign_width = self.assign_width;
+        // The end of the current run of let statements.
+        let mut assign_run_end = 0;
+        for (i, stmt) in b.stmts.iter().enumerate() {
+            if i >= assign_run_end {
+                self.assign_width = 0;
+                if config!(let_assign_align) && self.let_lhs(stmt).is_some() {
+                    assign_run_end = self.let_run_end(&b.stmts, i);
+                    self.assign_width = self.let_assign_width(&b.stmts[i..assign_run_end]);
+                }
+            }
             self.visit_stmt(&stmt)
         }
+        self.assign_width = outer_assign_width;
         match b.expr {
             Some(ref e) => {
                 self.format_missing_with_indent(e.span.lo);
@@ -80,7 +140,7 @@ impl<'a, 'v> visit::Visitor<'v> for FmtVisitor<'a> {
 
         self.block_indent -= config!(tab_spaces);
         // TODO we should compress any newlines here to just one
-        self.format_missing_with_indent(b.span.hi - BytePos(1));
+        self.format_missing_block_end(b.span.hi - BytePos(1));
         self.changes.push_str_span(b.span, "}");
         self.last_pos = b.span.hi;
     }
@@ -97,39 +157,42 @@ impl<'a, 'v> visit::Visitor<'v> for FmtVisitor<'a> {
         self.last_pos = s.lo;
 
         let indent = self.block_indent;
-        match fk {
+        let rewrite = match fk {
             visit::FkItemFn(ident,
                             ref generics,
                             ref unsafety,
                             ref constness,
                             ref abi,
                             vis) => {
-                let new_fn = self.rewrite_fn(indent,
-                                             ident,
-                                             fd,
-                                             None,
-                                             generics,
-                                             unsafety,
-                                             constness,
-                                             abi,
-                                             vis,
-                                             b.span.lo);
-                self.changes.push_str_span(s, &new_fn);
+                Some(self.rewrite_fn(indent,
+                                     ident,
+                                     fd,
+                                     None,
+                                     generics,
+                                     unsafety,
+                                     constness,
+                                     abi,
+                                     vis,
+                                     b.span.lo))
             }
             visit::FkMethod(ident, ref sig, vis) => {
-                let new_fn = self.rewrite_fn(indent,
-                                             ident,
-                                             fd,
-                                             Some(&sig.explicit_self),
-                                             &sig.generics,
-                                             &sig.unsafety,
-                                             &sig.constness,
-                                             &sig.abi,
-                                             vis.unwrap_or(ast::Visibility::Inherited),
-                                             b.span.lo);
-                self.changes.push_str_span(s, &new_fn);
-            }
-            visit::FkFnBlock(..) => {}
+                Some(self.rewrite_fn(indent,
+                                     ident,
+                                     fd,
+                                     Some(&sig.explicit_self),
+                                     &sig.generics,
+                                     &sig.unsafety,
+                                     &sig.constness,
+                                     &sig.abi,
+                                     vis.unwrap_or(ast::Visibility::Inherited),
+                                     b.span.lo))
+            }
+            visit::FkFnBlock(..) => None,
+        };
+        if let Some(rewrite) = rewrite {
+            // If the signature doesn't fit, we leave it as it is.
+            let new_fn = rewrite.unwrap_or_else(|| self.snippet(codemap::mk_sp(s.lo, b.span.lo)));
+            self.changes.push_str_span(s, &new_fn);
         }
 
         self.last_pos = b.span.lo;
@@ -137,6 +200,13 @@ impl<'a, 'v> visit::Visitor<'v> for FmtVisitor<'a> {
     }
 
     fn visit_item(&mut self, item: &'v ast::Item) {
+        if let ast::Item_::ItemUse(ref vp) = item.node {
+            if self.is_unused_use(vp) {
+                self.remove_use_item(item);
+                return;
+            }
+        }
+
         // Don't look at attributes for modules.
         // We want to avoid looking at attributes in another file, which the AST
         // doesn't distinguish. FIXME This is overly conservative and means we miss
@@ -153,37 +223,86 @@ impl<'a, 'v> visit::Visitor<'v> for FmtVisitor<'a> {
         match item.node {
             ast::Item_::ItemUse(ref vp) => {
                 self.format_missing_with_indent(item.span.lo);
-                match vp.node {
-                    ast::ViewPath_::ViewPathList(ref path, ref path_list) => {
-                        let block_indent = self.block_indent;
-                        let one_line_budget = config!(max_width) - block_indent;
-                        let multi_line_budget = config!(ideal_width) - block_indent;
-                        let new_str = self.rewrite_use_list(block_indent,
-                                                            one_line_budget,
-                                                            multi_line_budget,
-                                                            path,
-                                                            path_list,
-                                                            item.vis);
-                        self.changes.push_str_span(item.span, &new_str);
-                        self.last_pos = item.span.hi;
-                    }
-                    ast::ViewPath_::ViewPathGlob(_) => {
-                        // FIXME convert to list?
-                    }
-                    ast::ViewPath_::ViewPathSimple(_,_) => {}
+                if let Some(new_str) = self.rewrite_use(vp, item.vis, item.span) {
+                    self.changes.push_str_span(item.span, &new_str);
+                    self.last_pos = item.span.hi;
                 }
                 visit::walk_item(self, item);
             }
-            ast::Item_::ItemImpl(..) |
-            ast::Item_::ItemMod(_) |
-            ast::Item_::ItemTrait(..) => {
+            ast::Item_::ItemImpl(unsafety,
+                                 polarity,
+                                 ref generics,
+                                 ref trait_ref,
+                                 ref self_ty,
+                                 ref impl_items) => {
+                self.format_missing_with_indent(item.span.lo);
+
+                // FIXME this will give incorrect results if there is a { in a comment.
+                let snippet = self.snippet(codemap::mk_sp(self_ty.span.hi, item.span.hi));
+                let body_start = self_ty.span.hi + BytePos(snippet.find('{').unwrap() as u32 + 1);
+
+                let header_str = self.rewrite_impl_header(unsafety,
+                                                          polarity,
+                                                          generics,
+                                                          trait_ref.as_ref(),
+                                                          self_ty,
+                                                          body_start);
+                self.changes.push_str_span(item.span, &header_str);
+                self.format_item_body(item.span, body_start, impl_items.len(), |this| {
+                    for impl_item in impl_items {
+                        this.visit_impl_item(impl_item);
+                    }
+                });
+            }
+            ast::Item_::ItemTrait(unsafety, ref generics, ref bounds, ref trait_items) => {
+                self.format_missing_with_indent(item.span.lo);
+
+                // FIXME this will give incorrect results if there is a { in a comment.
+                let snippet = self.snippet(item.span);
+                let body_start = item.span.lo + BytePos(snippet.find('{').unwrap() as u32 + 1);
+
+                let header_str = self.rewrite_trait_header(item.vis,
+                                                           unsafety,
+                                                           item.ident,
+                                                           generics,
+                                                           bounds,
+                                                           body_start);
+                self.changes.push_str_span(item.span, &header_str);
+                self.format_item_body(item.span, body_start, trait_items.len(), |this| {
+                    for trait_item in trait_items {
+                        this.visit_trait_item(trait_item);
+                    }
+                });
+            }
+            ast::Item_::ItemForeignMod(ref foreign_mod) => {
+                self.format_missing_with_indent(item.span.lo);
+
+                // FIXME this will give incorrect results if there is a { in a comment.
+                let snippet = self.snippet(item.span);
+                let body_start = item.span.lo + BytePos(snippet.find('{').unwrap() as u32 + 1);
+
+                // Always write the ABI, `extern {` means `extern "C" {`.
+                let header_str = format!("extern {} {{", foreign_mod.abi);
+                self.changes.push_str_span(item.span, &header_str);
+                self.format_item_body(item.span, body_start, foreign_mod.items.len(), |this| {
+                    for foreign_item in &foreign_mod.items {
+                        this.visit_foreign_item(foreign_item);
+                    }
+                });
+            }
+            ast::Item_::ItemMod(_) if self.is_mod_decl(item) => {
+                self.format_missing_with_indent(item.span.lo);
+                self.changes.push_str_span(item.span, &rewrite_mod_decl(item));
+                self.last_pos = item.span.hi;
+            }
+            ast::Item_::ItemMod(_) => {
                 self.block_indent += config!(tab_spaces);
                 visit::walk_item(self, item);
                 self.block_indent -= config!(tab_spaces);
             }
             ast::Item_::ItemExternCrate(_) => {
                 self.format_missing_with_indent(item.span.lo);
-                let new_str = self.snippet(item.span);
+                let new_str = self.rewrite_extern_crate(item);
                 self.changes.push_str_span(item.span, &new_str);
                 self.last_pos = item.span.hi;
             }
@@ -196,6 +315,55 @@ impl<'a, 'v> visit::Visitor<'v> for FmtVisitor<'a> {
                                   item.span);
                 self.last_pos = item.span.hi;
             }
+            ast::Item_::ItemConst(ref ty, ref expr) => {
+                self.format_missing_with_indent(item.span.lo);
+                let new_str = self.rewrite_static("const",
+                                                  item.vis,
+                                                  item.ident,
+                                                  ty,
+                                                  ast::Mutability::MutImmutable,
+                                                  Some(&**expr));
+                self.changes.push_str_span(item.span, &new_str);
+                self.last_pos = item.span.hi;
+            }
+            ast::Item_::ItemStatic(ref ty, mutability, ref expr) => {
+                self.format_missing_with_indent(item.span.lo);
+                let new_str = self.rewrite_static("static",
+                                                  item.vis,
+                                                  item.ident,
+                                                  ty,
+                                                  mutability,
+                                                  Some(&**expr));
+                self.changes.push_str_span(item.span, &new_str);
+                self.last_pos = item.span.hi;
+            }
+            ast::Item_::ItemTy(ref ty, ref generics) => {
+                self.format_missing_with_indent(item.span.lo);
+                let new_str = self.rewrite_type_alias(item.vis,
+                                                      item.ident,
+                                                      ty,
+                                                      generics,
+                                                      item.span);
+                self.changes.push_str_span(item.span, &new_str);
+                self.last_pos = item.span.hi;
+            }
+            ast::Item_::ItemMac(ref mac) => {
+                self.format_missing_with_indent(item.span.lo);
+                // Anything we can't format is left for format_missing.
+                if let Some(new_str) = self.rewrite_macro_def(item.ident, mac) {
+                    self.changes.push_str_span(item.span, &new_str);
+                    self.last_pos = item.span.hi;
+                }
+            }
+            ast::Item_::ItemEnum(ref def, ref generics) => {
+                self.format_missing_with_indent(item.span.lo);
+                self.visit_enum(item.ident,
+                                item.vis,
+                                def,
+                                generics,
+                                item.span);
+                self.last_pos = item.span.hi;
+            }
             _ => {
                 visit::walk_item(self, item);
             }
@@ -207,19 +375,41 @@ impl<'a, 'v> visit::Visitor<'v> for FmtVisitor<'a> {
             return;
         }
 
-        if let ast::TraitItem_::MethodTraitItem(ref sig, None) = ti.node {
-            self.format_missing_with_indent(ti.span.lo);
+        match ti.node {
+            ast::TraitItem_::MethodTraitItem(ref sig, None) => {
+                self.format_missing_with_indent(ti.span.lo);
 
-            let indent = self.block_indent;
-            let new_fn = self.rewrite_required_fn(indent,
-                                                  ti.ident,
-                                                  sig,
-                                                  ti.span);
+                let indent = self.block_indent;
+                let new_fn = self.rewrite_required_fn(indent, ti.ident, sig, ti.span)
+                                 .unwrap_or_else(|| self.snippet(ti.span));
 
-            self.changes.push_str_span(ti.span, &new_fn);
-            self.last_pos = ti.span.hi;
+                self.changes.push_str_span(ti.span, &new_fn);
+                self.last_pos = ti.span.hi;
+            }
+            ast::TraitItem_::MethodTraitItem(_, Some(_)) => {}
+            ast::TraitItem_::ConstTraitItem(ref ty, ref expr) => {
+                self.format_missing_with_indent(ti.span.lo);
+                let new_const = self.rewrite_static("const",
+                                                    ast::Visibility::Inherited,
+                                                    ti.ident,
+                                                    ty,
+                                                    ast::Mutability::MutImmutable,
+                                                    expr.as_ref().map(|e| &**e));
+                self.changes.push_str_span(ti.span, &new_const);
+                self.last_pos = ti.span.hi;
+                return;
+            }
+            ast::TraitItem_::TypeTraitItem(ref bounds, ref ty) => {
+                self.format_missing_with_indent(ti.span.lo);
+                let new_ty = self.rewrite_assoc_type(ast::Visibility::Inherited,
+                                                     ti.ident,
+                                                     bounds,
+                                                     ty.as_ref().map(|t| &**t));
+                self.changes.push_str_span(ti.span, &new_ty);
+                self.last_pos = ti.span.hi;
+                return;
+            }
         }
-        // TODO format trait types
 
         visit::walk_trait_item(self, ti)
     }
@@ -228,7 +418,53 @@ impl<'a, 'v> visit::Visitor<'v> for FmtVisitor<'a> {
         if self.visit_attrs(&ii.attrs) {
             return;
         }
-        visit::walk_impl_item(self, ii)
+
+        match ii.node {
+            ast::ImplItem_::ConstImplItem(ref ty, ref expr) => {
+                self.format_missing_with_indent(ii.span.lo);
+                let new_const = self.rewrite_static("const",
+                                                    ii.vis,
+                                                    ii.ident,
+                                                    ty,
+                                                    ast::Mutability::MutImmutable,
+                                                    Some(&**expr));
+                self.changes.push_str_span(ii.span, &new_const);
+                self.last_pos = ii.span.hi;
+            }
+            ast::ImplItem_::TypeImplItem(ref ty) => {
+                self.format_missing_with_indent(ii.span.lo);
+                let new_ty = self.rewrite_assoc_type(ii.vis, ii.ident, &[], Some(&**ty));
+                self.changes.push_str_span(ii.span, &new_ty);
+                self.last_pos = ii.span.hi;
+            }
+            _ => visit::walk_impl_item(self, ii),
+        }
+    }
+
+    fn visit_foreign_item(&mut self, fi: &'v ast::ForeignItem) {
+        if self.visit_attrs(&fi.attrs) {
+            return;
+        }
+        self.format_missing_with_indent(fi.span.lo);
+
+        let new_str = match fi.node {
+            ast::ForeignItem_::ForeignItemFn(ref fd, ref generics) => {
+                let indent = self.block_indent;
+                self.rewrite_foreign_fn(indent, fi.ident, fd, generics, fi.vis, fi.span)
+                    .unwrap_or_else(|| self.snippet(fi.span))
+            }
+            ast::ForeignItem_::ForeignItemStatic(ref ty, is_mutable) => {
+                let mutability = if is_mutable {
+                    ast::Mutability::MutMutable
+                } else {
+                    ast::Mutability::MutImmutable
+                };
+                self.rewrite_static("static", fi.vis, fi.ident, ty, mutability, None)
+            }
+        };
+
+        self.changes.push_str_span(fi.span, &new_str);
+        self.last_pos = fi.span.hi;
     }
 
     fn visit_mac(&mut self, mac: &'v ast::Mac) {
@@ -241,7 +477,7 @@ impl<'a, 'v> visit::Visitor<'v> for FmtVisitor<'a> {
            self.codemap.lookup_char_pos(m.inner.lo).file.name {
             return;
         }
-        visit::walk_mod(self, m);
+        self.visit_mod_items(&m.items);
     }
 }
 
@@ -252,18 +488,46 @@ impl<'a> FmtVisitor<'a> {
             changes: ChangeSet::from_codemap(codemap),
             last_pos: BytePos(0),
             block_indent: 0,
+            extern_crates: Vec::new(),
+            module_names: HashSet::new(),
+            glob_imports: Vec::new(),
+            unused_imports: Vec::new(),
+            assign_width: 0,
         }
     }
 
+    // Formats the items between the braces of an impl or trait, including the
+    // closing brace. visit_items is responsible for visiting each item.
+    fn format_item_body<F>(&mut self,
+                           span: Span,
+                           body_start: BytePos,
+                           item_count: usize,
+                           visit_items: F)
+        where F: FnOnce(&mut FmtVisitor<'a>)
+    {
+        self.last_pos = body_start;
+
+        let body_end = span.hi - BytePos(1);
+        // Keep empty bodies on one line, e.g., `impl Send for Foo {}`.
+        if item_count > 0 || self.snippet(codemap::mk_sp(body_start, body_end)).trim().len() > 0 {
+            self.block_indent += config!(tab_spaces);
+            visit_items(self);
+            self.block_indent -= config!(tab_spaces);
+            self.format_missing_block_end(body_end);
+        }
+
+        self.changes.push_str_span(span, "}");
+        self.last_pos = span.hi;
+    }
+
     pub fn snippet(&self, span: Span) -> String {
-        match self.codemap.span_to_snippet(span) {
-            Ok(s) => s,
-            Err(_) => {
-                println!("Couldn't make snippet for span {:?}->{:?}",
-                         self.codemap.lookup_char_pos(span.lo),
-                         self.codemap.lookup_char_pos(span.hi));
-                "".to_owned()
-            }
+        self.get_context().snippet(span)
+    }
+
+    pub fn get_context(&self) -> RewriteContext {
+        RewriteContext {
+            codemap: self.codemap,
+            block_indent: self.block_indent,
         }
     }
 
@@ -287,37 +551,68 @@ impl<'a> FmtVisitor<'a> {
         }
     }
 
-    fn rewrite_attrs(&self, attrs: &[ast::Attribute], indent: usize) -> Option<String> {
+    pub fn rewrite_attrs(&self, attrs: &[ast::Attribute], indent: usize) -> Option<String> {
         let mut result = String::new();
         let indent = utils::make_indent(indent);
 
-        for (i, a) in attrs.iter().enumerate() {
+        let doc_style = config!(normalize_doc_comments);
+
+        let mut i = 0;
+        while i < attrs.len() {
+            let a = &attrs[i];
             if is_skip(&a.node.value) {
                 return None;
             }
 
-            let a_str = self.snippet(a.span);
-
             if i > 0 {
                 let comment = self.snippet(codemap::mk_sp(attrs[i-1].span.hi, a.span.lo));
-                // This particular horror show is to preserve line breaks in between doc
-                // comments. An alternative would be to force such line breaks to start
-                // with the usual doc comment token.
-                let multi_line = a_str.starts_with("//") && comment.matches('\n').count() > 1;
+                // Keep (at most one) blank line between attributes, e.g., to
+                // separate paragraphs of doc comments.
+                let blank_line = comment.matches('\n').count() > 1;
                 let comment = comment.trim();
                 if comment.len() > 0 {
                     result.push_str(&indent);
                     result.push_str(comment);
                     result.push('\n');
-                } else if multi_line {
+                } else if blank_line {
                     result.push('\n');
                 }
                 result.push_str(&indent);
             }
 
-            result.push_str(&a_str);
+            // When writing block doc comments, consecutive doc comments are
+            // merged into one. When formatting code in doc comments, we need
+            // the whole of each paragraph.
+            let merge_docs = doc_style == DocCommentStyle::Block ||
+                             config!(format_code_in_doc_comments);
+            let mut run_end = i + 1;
+            if merge_docs && is_doc_comment(a) {
+                while run_end < attrs.len() && is_doc_comment(&attrs[run_end]) &&
+                      attrs[run_end].node.style == a.node.style {
+                    let gap = self.snippet(codemap::mk_sp(attrs[run_end - 1].span.hi,
+                                                          attrs[run_end].span.lo));
+                    let blank_line = gap.matches('\n').count() > 1;
+                    if gap.trim().len() > 0 ||
+                       (blank_line && doc_style != DocCommentStyle::Block) {
+                        break;
+                    }
+                    run_end += 1;
+                }
+            }
+
+            match rewrite_doc_comment(&attrs[i..run_end], doc_style, indent.len()) {
+                Some(doc_str) => result.push_str(&doc_str),
+                None => {
+                    // Not a doc comment or can't be rewritten, keep the
+                    // original attribute. Any other doc comments in the run
+                    // are written one at a time, so that they are re-indented.
+                    result.push_str(&self.snippet(a.span));
+                    run_end = i + 1;
+                }
+            }
+            i = run_end;
 
-            if i < attrs.len() -1 {
+            if i < attrs.len() {
                 result.push('\n');
             }
         }
@@ -26,8 +26,9 @@ use rustfmt::*;
 fn idempotent_tests() {
     println!("Idempotent tests:");
 
-    // Get all files in the tests/idem directory
+    // Get all files in the tests/idem and tests/target directories
     let files = fs::read_dir("tests/idem").unwrap();
+    let files = files.chain(fs::read_dir("tests/target").unwrap());
     let files = files.chain(fs::read_dir("tests").unwrap());
     let files = files.chain(fs::read_dir("src/bin").unwrap());
     // turn a DirEntry into a String that represents the relative path to the file
@@ -35,7 +36,32 @@ fn idempotent_tests() {
     // hack because there's no `IntoIterator` impl for `[T; N]`
     let files = files.chain(Some("src/lib.rs".to_owned()).into_iter());
 
-    // For each file, run rustfmt and collect the output
+    let (count, fails) = check_files(files);
+
+    // Display results
+    println!("Ran {} idempotent tests; {} failures.", count, fails);
+    assert!(fails == 0, "{} idempotent tests failed", fails);
+}
+
+// Tests where the input is not already formatted: each file in tests/source
+// must be formatted to the file with the same name in tests/target.
+#[test]
+fn system_tests() {
+    println!("System tests:");
+
+    let files = fs::read_dir("tests/source").unwrap();
+    let files = files.map(|e| e.unwrap().path().to_str().unwrap().to_owned());
+
+    let (count, fails) = check_files(files);
+
+    // Display results
+    println!("Ran {} system tests; {} failures.", count, fails);
+    assert!(fails == 0, "{} system tests failed", fails);
+}
+
+// For each file, run rustfmt and collect the output. Returns the number of
+// files checked and the number of failures.
+fn check_files<I: Iterator<Item = String>>(files: I) -> (usize, usize) {
     let mut count = 0;
     let mut fails = 0;
     for file_name in files.filter(|f| f.ends_with(".rs")) {
@@ -50,9 +76,7 @@ fn idempotent_tests() {
         count += 1;
     }
 
-    // Display results
-    println!("Ran {} idempotent tests; {} failures.", count, fails);
-    assert!(fails == 0, "{} idempotent tests failed", fails);
+    (count, fails)
 }
 
 // Compare output to input.
@@ -66,27 +90,67 @@ fn print_mismatches(result: HashMap<String, String>) {
 static HANDLE_RESULT: &'static Fn(HashMap<String, String>) = &handle_result;
 
 pub fn idempotent_check(filename: String) -> Result<(), HashMap<String, String>> {
+    let config = get_config(&filename);
     let args = vec!["rustfmt".to_owned(), filename];
-    let mut def_config_file = fs::File::open("default.toml").unwrap();
-    let mut def_config = String::new();
-    def_config_file.read_to_string(&mut def_config).unwrap();
     // this thread is not used for concurrency, but rather to workaround the issue that the passed
     // function handle needs to have static lifetime. Instead of using a global RefCell, we use
     // panic to return a result in case of failure. This has the advantage of smoothing the road to
     // multithreaded rustfmt
     thread::catch_panic(move || {
-        run(args, WriteMode::Return(HANDLE_RESULT), &def_config);
+        run(args, WriteMode::Return(HANDLE_RESULT), &config);
     }).map_err(|any|
         *any.downcast().unwrap()
     )
 }
 
+// The default config, with any options set in the test file by comments of the
+// form `// rustfmt-<option>: <value>` in place of the defaults. Values are
+// written as in toml, e.g., `// rustfmt-indent_style: "Block"`.
+fn get_config(file_name: &str) -> String {
+    let mut def_config_file = fs::File::open("default.toml").unwrap();
+    let mut def_config = String::new();
+    def_config_file.read_to_string(&mut def_config).unwrap();
+
+    let mut f = fs::File::open(file_name).unwrap();
+    let mut text = String::new();
+    f.read_to_string(&mut text).unwrap();
+
+    let prefix = "// rustfmt-";
+    let options: Vec<(&str, &str)> = text.lines()
+                                          .filter(|l| l.starts_with(prefix))
+                                          .filter_map(|l| {
+                                              let option = &l[prefix.len()..];
+                                              option.find(':').map(|i| {
+                                                  (option[..i].trim(), option[i + 1..].trim())
+                                              })
+                                          })
+                                          .collect();
+
+    def_config.lines()
+              .map(|line| {
+                  let name = line.split('=').next().unwrap().trim();
+                  match options.iter().find(|&&(option, _)| option == name) {
+                      Some(&(_, value)) => format!("{} = {}", name, value),
+                      None => line.to_owned(),
+                  }
+              })
+              .collect::<Vec<_>>()
+              .connect("\n")
+}
+
+// The file we expect the output for file_name to match: for files in
+// tests/source, the file with the same name in tests/target, otherwise the
+// file itself.
+fn get_target(file_name: &str) -> String {
+    file_name.replace("tests/source/", "tests/target/")
+}
+
 // Compare output to input.
 fn handle_result(result: HashMap<String, String>) {
     let mut failures = HashMap::new();
 
     for (file_name, fmt_text) in result {
-        let mut f = fs::File::open(&file_name).unwrap();
+        let mut f = fs::File::open(&get_target(&file_name)).unwrap();
         let mut text = String::new();
         // TODO: speedup by running through bytes iterator
         f.read_to_string(&mut text).unwrap();
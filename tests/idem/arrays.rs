@@ -0,0 +1,11 @@
+// Array literals.
+
+fn main() {
+    let x = [1, 2, 3];
+    let zeros = [0u8; 256];
+    let empty: [u32; 0] = [];
+    let squares = [   0,    1,    4,    9,   16,   25,   36,   49,   64,   81,  100,  121,  144,
+                    169,  196,  225,  256,  289,  324,  361,  400,  441,  484,  529,  576,  625,
+                    676,  729,  784,  841,  900,  961, 1024, 1089, 1156, 1225, 1296, 1369, 1444,
+                   1521];
+}
@@ -6,6 +6,7 @@ extern crate Bar;
 #[Attr2]
 #[Attr2]
 extern crate Baz;
+extern crate Qux as Quux;
 
 fn foo() {
     extern crate Foo;
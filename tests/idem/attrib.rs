@@ -29,4 +29,11 @@ impl Bar {
     #[another_attribute]
     fn f3(self) -> Dog {
     }
+
+    #[doc = "Blah blah blah."]
+    #[an_attribute]
+
+    #[another_attribute]
+    fn f4(self) {
+    }
 }
@@ -0,0 +1,14 @@
+// Test comments in lists are preserved.
+
+use foo::{self, // Comment on self
+          bar, /* Comment on bar */
+          baz};
+
+fn main() {
+    foo(a, // Comment on a
+        // Comment on b
+        b);
+
+    let x = Foo { a: 1 /* Comment on a */, b: 2 };
+    let y = (a /* Comment on a */, b);
+}
@@ -0,0 +1,16 @@
+// Test comments are re-indented.
+
+/*
+ * A block comment
+ * with stars.
+ */
+fn main() {
+    // A comment at the start of the block.
+    let x = 1;
+
+    /* A block comment
+       spanning lines. */
+    let y = 2; // A trailing comment.
+
+    // A comment at the end of the block.
+}
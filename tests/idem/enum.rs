@@ -0,0 +1,36 @@
+// Enums test
+
+#[atrr]
+pub enum Test {
+    A,
+    B(u32, A /* comment */),
+    /// Doc comment
+    C,
+}
+
+pub enum Foo<'a, Y: Baz>
+    where X: Whatever
+{
+    A,
+}
+
+enum EmtpyWithComment {
+    // Some comment
+}
+
+// C-style enum
+enum Bar {
+    A = 1,
+    #[someAttr(test)]
+    B = 2, // comment
+    C,
+}
+
+enum StructLikeVariants {
+    Normal(u32, String),
+    StructLike { x: i32, y: f64 },
+    LongStructLike {
+        aaaaaaaaaaaaaaaaaaaaaaaaaaaa: AAAAAAAAAAAAAAAAAAAA,
+        bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb: Option<BBBBBBBBBBBBBBBB>,
+    },
+}
@@ -0,0 +1,31 @@
+// Test extern blocks.
+
+extern "C" {
+    fn c_func(x: *mut *mut libc::c_void);
+
+    #[test123]
+    fn foo() -> uint64_t;
+
+    pub fn bar();
+}
+
+extern "C" {
+    fn DMR_GetDevice(pHDev: *mut HDEV,
+                     searchMode: DeviceSearchMode,
+                     pSearchString: *const c_char,
+                     devNr: c_uint,
+                     wildcard: c_char)
+                     -> TDMR_ERROR;
+
+    fn quux() -> (); // Post comment
+}
+
+extern "Rust" {
+    static ext: u32;
+    // Some comment.
+    pub static mut var: SomeType;
+}
+
+extern "C" {
+    fn printf(format: *const c_char, ...) -> c_int;
+}
@@ -0,0 +1,26 @@
+// Test impl headers
+
+impl Foo for Bar {
+    fn foo() {
+        "hi"
+    }
+}
+
+impl<T> Foo for Bar<T>
+    where T: Baz
+{
+    fn foo() {
+    }
+}
+
+impl<'a, T: Clone> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+}
+
+unsafe impl Send for Foo {}
+
+impl !Sync for Foo {}
+
+impl<T> Foo<T> {
+    // A comment in an impl.
+}
@@ -9,6 +9,11 @@ use {Foo, Bar};
 use Foo::{Bar, Baz};
 pub use syntax::ast::{Expr_, Expr, ExprAssign, ExprCall, ExprMethodCall, ExprPath};
 
+// Simple and glob imports.
+use std::io::Result as IoResult;
+pub use syntax::ast::*;
+use ::std::mem;
+
 mod Foo {
     pub use syntax::ast::{ItemForeignMod, ItemImpl, ItemMac, ItemMod, ItemStatic, ItemDefaultImpl};
 
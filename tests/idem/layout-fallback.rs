@@ -0,0 +1,12 @@
+// Lists which don't fit with visual indentation.
+
+fn main() {
+    let configuration = ApplicationConfigurationWithAnExtremelyLongTypeNameHere {
+        name: "rustfmt",
+        maximum_width: 100,
+    };
+
+    let message = format_message_for_user(
+        "this string is much too long to fit after the opening parenthesis", 42
+    );
+}
@@ -0,0 +1,20 @@
+// Test macro definitions.
+
+macro_rules! add_one {
+    ($x:expr) => {
+        $x + 1
+    };
+    ($x:expr, $y:expr) => {
+        add($x, $y)
+    };
+}
+
+macro_rules! empty {
+    () => {};
+}
+
+macro_rules! repeated {
+    ($($x:expr),*) => {
+        vec![$($x),*]
+    };
+}
@@ -0,0 +1,20 @@
+// Test macro invocations.
+
+fn main() {
+    foo!();
+
+    bar!(a, b, c);
+
+    baz!(1 + 2 + 3, quux.kaas());
+
+    let v = vec![1, 2, 3];
+
+    let s = format!("{} {}", a, b);
+
+    println!("{}",
+             aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa);
+
+    let x = try!(foo());
+
+    not_list!(key => value);
+}
@@ -0,0 +1,13 @@
+// Test paths.
+
+fn main() {
+    let x = Vec::<HashMap<u32, String>>::new();
+    let y = <T as Default>::default();
+    let z = <Vec<u32>>::new();
+    let w = ::std::mem::size_of::<u64>();
+}
+
+fn foo(f: Box<Fn(u32, &str) -> bool>, x: <T as Iterator>::Item)
+    where T: Iterator<Item = u8>
+{
+}
@@ -0,0 +1,16 @@
+// Test const, static and type alias items.
+
+const FILE_GENERIC_READ: DWORD = STANDARD_RIGHTS_READ | FILE_READ_DATA | FILE_READ_ATTRIBUTES;
+
+static boolnames: &'static [&'static str] = &["bw", "am", "xsb", "xhp", "xenl", "eo", "gn", "hc"];
+
+static mut name: SomeType = SomeType { a: 1, b: 2 };
+
+pub static FOO: Option<u32> = None;
+
+pub type Foo<'a, T> = Result<&'a T, Error>;
+
+type Callback = Box<Fn(u32) -> bool + Send>;
+
+const LOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOONG: u32 =
+    1 + 2 + 3;
@@ -18,3 +18,22 @@ trait Foo {
     fn read(&mut self, x: BufReader<R> /* Used to be MemReader */)
         where R: Read;
 }
+
+trait Bar: Clone + Send + 'static {
+    type Item: Debug;
+    type Other: Clone = u32;
+    const ID: u32;
+    const DEFAULT: u32 = 42;
+}
+
+pub unsafe trait Baz<T>: Foo<T>
+    where T: Clone
+{
+    fn baz(&self) -> T;
+}
+
+impl Bar for Qux {
+    type Item = u32;
+    type Other = u64;
+    const ID: u32 = 1;
+}
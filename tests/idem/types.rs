@@ -0,0 +1,15 @@
+// Test type formatting.
+
+struct Foo {
+    a: HashMap<Vec<u32>, String>,
+    b: &'a mut [u8],
+    c: *const (i32, u32),
+    d: fn(u32, &str) -> bool,
+    e: Box<Iterator<Item = u32> + Send + 'static>,
+    f: (A,),
+    g: [u8; 4],
+    h: unsafe extern "C" fn(*mut c_void, ...),
+}
+
+fn foo(a: &mut Vec<(String, Option<u32>)>, b: *mut T) -> Option<(u32, &'static str)> {
+}
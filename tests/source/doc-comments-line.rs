@@ -0,0 +1,15 @@
+// rustfmt-normalize_doc_comments: "Line"
+
+/**
+ * Documentation for foo.
+ *
+ * More documentation.
+ */
+fn foo() {
+}
+
+impl Foo {
+    /** Documentation for a method. */
+    fn method(&self) {
+    }
+}
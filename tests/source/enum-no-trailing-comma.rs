@@ -0,0 +1,12 @@
+// rustfmt-enum_trailing_comma: false
+
+enum Foo {
+    A,
+    B(u32),
+    C { x: i32 },
+}
+
+enum Bar {
+    A = 1,
+    B = 2 // A comment.
+}
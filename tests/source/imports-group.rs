@@ -0,0 +1,21 @@
+// rustfmt-group_imports: true
+
+use foo::Foo;
+use std::io;
+use bar::Bar;
+
+extern crate foo;
+
+mod a {
+    extern crate baz;
+
+    use baz::Baz;
+    use foo::Foo;
+    use std::fmt;
+}
+
+mod b {
+    use baz::Baz;
+    use foo::Foo;
+    use std::fmt;
+}
@@ -0,0 +1,4 @@
+// rustfmt-imports_granularity: "Item"
+
+use a::{b, c};
+use d::{self, e};
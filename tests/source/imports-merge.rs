@@ -0,0 +1,9 @@
+// rustfmt-imports_granularity: "Merge"
+
+use a::b;
+use a::c;
+use d::e::{f, g};
+use d::e::h;
+pub use a::x;
+#[cfg(test)]
+use a::y;
@@ -0,0 +1,8 @@
+// rustfmt-reorder_imports: true
+
+use std::io::Write;
+use foo::{bar, Baz, io, self, quux};
+
+fn main() {
+    quux::f();
+}
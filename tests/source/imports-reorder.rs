@@ -0,0 +1,14 @@
+// rustfmt-reorder_imports: true
+
+use std::io;
+pub use foo::bar;
+#[cfg(unix)]
+use bar::baz; // Only on unix.
+
+use zed::a;
+// A comment separates runs.
+use beta::c;
+use alpha::b;
+
+fn main() {
+}
@@ -0,0 +1,12 @@
+// rustfmt-item_brace_style: "AlwaysNextLine"
+
+impl Foo for Bar {
+    fn foo() {
+    }
+}
+
+impl<T> Foo for Bar<T> where T: Baz
+{
+    fn foo() {
+    }
+}
@@ -0,0 +1,12 @@
+// rustfmt-let_assign_align: true
+
+fn main() {
+    let a = 1;
+    let bcd: u32 = 2;
+    let mut efgh = 3;
+
+    let x = 4;
+    let yy:u8=5;
+    let zzz;
+    zzz = x + yy;
+}
@@ -0,0 +1,10 @@
+// rustfmt-reorder_extern_crates: true
+
+extern crate syntax;
+extern  crate log as logger;
+#[macro_use]
+extern crate alpha;
+extern crate zed; // The last.
+
+fn main() {
+}
@@ -0,0 +1,11 @@
+// rustfmt-reorder_mods: true
+
+#[path = "reorder-mods/c.rs"]
+mod zed;
+#[path = "reorder-mods/a.rs"]
+pub  mod beta; // The second.
+#[path = "reorder-mods/b.rs"]
+mod alpha;
+
+fn main() {
+}
@@ -0,0 +1,5 @@
+// rustfmt-struct_lit_field_align: true
+
+fn main() {
+    Fooooooooooooooooooooooooooooooooooooooooooooooooooooo { a: foo(), bcd: bar(), efghij: baz(), k: qux() };
+}
@@ -0,0 +1,10 @@
+// rustfmt-where_brace_newline: false
+
+fn foo<T>(a: T) -> T where T: Clone {
+    a
+}
+
+impl<T> Foo for Bar<T> where T: Baz {
+    fn bar() {
+    }
+}
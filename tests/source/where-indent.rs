@@ -0,0 +1,11 @@
+// rustfmt-where_indent: "Inherit"
+
+fn foo<T>(a: T) -> T where T: Clone {
+    a
+}
+
+impl Foo {
+    fn bar<U>(&self) -> U where U: Default {
+        U::default()
+    }
+}
@@ -0,0 +1,10 @@
+// rustfmt-where_single_line: true
+// rustfmt-where_trailing_comma: "Always"
+
+fn foo<T, U>(a: T, b: U) -> T where T: Clone, U: Copy {
+    a
+}
+
+fn bar<T, U>(a: T, b: U) -> T where T: Clone + Default + PartialEq + Debug, U: Copy + Clone + Default + PartialOrd + Debug {
+    a
+}
@@ -0,0 +1,11 @@
+// rustfmt-wrap_comments: true
+
+fn foo() {
+}
+
+// A comment which is much too long to fit on one line, so if wrap_comments is set it will be
+// wrapped on to the next line.
+//// A comment with four slashes, which is also too long to fit on one line and must keep its
+//// slashes when it is wrapped.
+fn bar() {
+}
@@ -0,0 +1,7 @@
+// rustfmt-const_assign_align: true
+
+const A: u32                 = 1;
+pub const BCD: u32           = 2;
+static mut EFGH: Option<u32> = None;
+
+const X: u8 = 3;
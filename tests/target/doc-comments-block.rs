@@ -0,0 +1,17 @@
+// rustfmt-normalize_doc_comments: "Block"
+
+/**
+ * Documentation for foo.
+ *
+ * More documentation.
+ */
+fn foo() {
+}
+
+impl Foo {
+    /**
+     * Documentation for a method.
+     */
+    fn method(&self) {
+    }
+}
@@ -0,0 +1,38 @@
+// rustfmt-format_code_in_doc_comments: true
+
+/// Documentation with some code.
+///
+/// ```
+/// let x = foo(a, b);
+/// ```
+fn foo() {
+}
+
+/// Code which starts with an attribute.
+///
+/// ```
+/// #[inline]
+/// fn bar(a: u32) -> u32 {
+///     a
+/// }
+/// ```
+fn bar() {
+}
+
+/// Code which starts with a doc comment.
+///
+/// ```
+/// /// Adds one.
+/// fn add_one(x: u32) -> u32 {
+///     x + 1
+/// }
+/// ```
+fn baz() {
+}
+
+impl Foo {
+    /// Documentation without any code.
+    /// More documentation.
+    fn method(&self) {
+    }
+}
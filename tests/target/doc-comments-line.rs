@@ -0,0 +1,13 @@
+// rustfmt-normalize_doc_comments: "Line"
+
+/// Documentation for foo.
+///
+/// More documentation.
+fn foo() {
+}
+
+impl Foo {
+    /// Documentation for a method.
+    fn method(&self) {
+    }
+}
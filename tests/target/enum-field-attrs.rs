@@ -0,0 +1,15 @@
+// Attributes on the fields of struct-like variants
+
+enum Foo {
+    A {
+        #[cfg(unix)]
+        x: i32,
+        y: f64,
+    },
+    B {
+        /// The first field.
+        x: i32,
+        #[rustfmt_skip]
+        y:  f64,
+    },
+}
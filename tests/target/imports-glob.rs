@@ -0,0 +1,7 @@
+// rustfmt-report_glob_imports: true
+
+use std::io::*;
+pub use foo::bar::*;
+
+fn main() {
+}
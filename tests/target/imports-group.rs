@@ -0,0 +1,26 @@
+// rustfmt-group_imports: true
+
+use std::io;
+
+use foo::Foo;
+
+use bar::Bar;
+
+extern crate foo;
+
+mod a {
+    extern crate baz;
+
+    use std::fmt;
+
+    use baz::Baz;
+    use foo::Foo;
+}
+
+mod b {
+    use std::fmt;
+
+    use foo::Foo;
+
+    use baz::Baz;
+}
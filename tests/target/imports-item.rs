@@ -0,0 +1,6 @@
+// rustfmt-imports_granularity: "Item"
+
+use a::b;
+use a::c;
+use d;
+use d::e;
@@ -0,0 +1,7 @@
+// rustfmt-imports_granularity: "Merge"
+
+use a::{b, c};
+use d::e::{f, g, h};
+pub use a::x;
+#[cfg(test)]
+use a::y;
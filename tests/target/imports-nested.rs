@@ -0,0 +1,5 @@
+// rustfmt-imports_granularity: "Nested"
+
+use a::b;
+use a::c::d;
+use a::c::e;
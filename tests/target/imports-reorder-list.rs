@@ -0,0 +1,8 @@
+// rustfmt-reorder_imports: true
+
+use foo::{self, io, quux, Baz, bar};
+use std::io::Write;
+
+fn main() {
+    quux::f();
+}
@@ -0,0 +1,14 @@
+// rustfmt-reorder_imports: true
+
+#[cfg(unix)]
+use bar::baz; // Only on unix.
+pub use foo::bar;
+use std::io;
+
+use zed::a;
+// A comment separates runs.
+use alpha::b;
+use beta::c;
+
+fn main() {
+}
@@ -0,0 +1,41 @@
+// rustfmt-remove_unused_imports: true
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+#[allow(unused_imports)]
+use std::rc::Rc;
+
+mod foo {
+    #![allow(unused_imports)]
+
+    use std::cell::Cell;
+}
+
+mod bar {
+    use std::cell::RefCell;
+
+    #[cfg(test)]
+    fn new_cell() -> RefCell<u32> {
+        RefCell::new(0)
+    }
+}
+
+fn show<T: Debug>(_: T) {
+}
+
+fn main() {
+    let map = HashMap::<u32, u32>::new();
+    show(map);
+}
+
+// A cfg'd module doesn't stop the imports above from being removed.
+#[cfg(test)]
+mod tests {
+    use super::show;
+
+    #[test]
+    fn shows() {
+        show(0);
+    }
+}
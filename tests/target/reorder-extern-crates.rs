@@ -0,0 +1,10 @@
+// rustfmt-reorder_extern_crates: true
+
+#[macro_use]
+extern crate alpha;
+extern crate log as logger;
+extern crate syntax;
+extern crate zed; // The last.
+
+fn main() {
+}
@@ -0,0 +1,4 @@
+// A module for reorder-mods.rs.
+
+pub fn a() {
+}
@@ -0,0 +1,7 @@
+// rustfmt-struct_field_align: true
+
+struct Foo {
+    a:       u32,
+    pub bcd: String,
+    efgh:    Vec<u8>,
+}
@@ -0,0 +1,6 @@
+// rustfmt-trailing_comment_align: true
+
+fn foo(a: u32,     // The first.
+       bcdef: u32, // The second.
+       g: u32) {
+}
@@ -0,0 +1,21 @@
+// rustfmt-indent_style: "Block"
+
+fn foo<T, U>(a: T, b: U) -> T
+where
+    T: Clone,
+    U: Copy
+{
+    a
+}
+
+impl<T> Foo for Bar<T>
+where
+    T: Baz
+{
+    fn bar<U>(&self) -> U
+    where
+        U: Default
+    {
+        U::default()
+    }
+}
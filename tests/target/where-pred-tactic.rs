@@ -0,0 +1,7 @@
+// rustfmt-where_pred_tactic: "Mixed"
+
+fn foo<T, U>(a: T, b: U) -> T
+    where T: Clone, U: Copy
+{
+    a
+}
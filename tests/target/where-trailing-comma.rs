@@ -0,0 +1,8 @@
+// rustfmt-where_trailing_comma: "Always"
+
+fn foo<T, U>(a: T, b: U) -> T
+    where T: Clone,
+          U: Copy,
+{
+    a
+}